@@ -1,7 +1,17 @@
+use std::time::{Duration, Instant};
+
 use chrono::{DateTime, Local};
 
-use crate::battery::BatterySample;
-use crate::history::History;
+use crate::battery::{BatterySample, ChargeThresholds};
+use crate::config::{Config, Sampling};
+use crate::error::Error;
+use crate::estimate::{self, ChargeCurve, Remaining};
+use crate::health::{BatteryHealth, HealthLog};
+use crate::history::{History, SessionKind, SessionStats};
+use crate::lock::WriterLock;
+use crate::recorder::{SessionChange, SessionEvent};
+use crate::rollup::{Resolution, Rollup, Stat};
+use crate::source::BatterySource;
 
 /// Which view the app is showing
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -10,8 +20,51 @@ pub enum View {
     Dashboard,
     /// History chart for all recorded data
     HistoryChart,
-    /// Charge session detail view
-    SessionDetail(usize), // index into charge_sessions
+    /// Charge or discharge session detail view
+    SessionDetail(SessionKind, DateTime<Local>), // start time of the session
+    /// Battery health: cycle count and wear over months
+    Health,
+    /// Scrollable list of all charge or discharge sessions
+    SessionList,
+}
+
+/// Sort key for the session list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionSort {
+    Date,
+    Duration,
+    Energy,
+    AveragePower,
+}
+
+impl SessionSort {
+    /// Next sort key, wrapping around
+    pub fn next(self) -> Self {
+        match self {
+            SessionSort::Date => SessionSort::Duration,
+            SessionSort::Duration => SessionSort::Energy,
+            SessionSort::Energy => SessionSort::AveragePower,
+            SessionSort::AveragePower => SessionSort::Date,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SessionSort::Date => "date",
+            SessionSort::Duration => "duration",
+            SessionSort::Energy => "energy",
+            SessionSort::AveragePower => "average W",
+        }
+    }
+
+    fn key(self, session: &dyn SessionStats) -> f64 {
+        match self {
+            SessionSort::Date => session.start_time().timestamp() as f64,
+            SessionSort::Duration => session.duration_secs(),
+            SessionSort::Energy => session.energy_wh(),
+            SessionSort::AveragePower => session.average_watts(),
+        }
+    }
 }
 
 /// Chart viewport for zoom/pan
@@ -94,6 +147,15 @@ impl ChartViewport {
     }
 }
 
+/// Lines of a history chart: raw values or rollup averages, plus the
+/// minimum and maximum of each bucket when showing rollups
+#[derive(Debug, Default)]
+pub struct ChartLines {
+    pub values: Vec<(f64, f64)>,
+    pub min: Vec<(f64, f64)>,
+    pub max: Vec<(f64, f64)>,
+}
+
 /// Main application state
 pub struct App {
     pub view: View,
@@ -102,16 +164,71 @@ pub struct App {
     pub session_viewport: ChartViewport,
     pub running: bool,
     pub battery_name: String,
+    /// Individual batteries that can be selected (empty on single-battery systems)
+    pub batteries: Vec<String>,
+    /// Whether history is loaded from and saved to disk
+    pub persist: bool,
+    /// Held while this process is the one writing the history
+    writer_lock: Option<WriterLock>,
+    /// Process id of the recorder whose history is being followed
+    pub recorder_pid: Option<u32>,
+    /// Latest session start or end reported by the recorder
+    pub last_event: Option<SessionEvent>,
+    /// Battery shown on the dashboard and history chart (`None` = combined pack)
+    pub selected_battery: Option<String>,
     pub last_sample: Option<BatterySample>,
+    /// Latest health reading for each battery
+    pub health: Vec<BatteryHealth>,
+    /// Daily health snapshots for the health view
+    pub health_log: HealthLog,
+    /// Current firmware charge thresholds
+    pub thresholds: Option<ChargeThresholds>,
+    /// Charging slowdown learned from past sessions, for time to full
+    charge_curve: ChargeCurve,
+    /// Why the latest battery reading failed, until one succeeds
+    pub sample_error: Option<Error>,
+    /// Why the latest save failed, until one succeeds
+    pub save_error: Option<Error>,
+    /// Session list ordering (descending unless `session_sort_ascending`)
+    pub session_sort: SessionSort,
+    pub session_sort_ascending: bool,
+    /// Highlighted row in the session list
+    pub session_list_selected: usize,
+    /// Whether the session list shows charge or discharge sessions
+    pub session_list_kind: SessionKind,
     pub tick_count: u64,
+    /// Sampling and autosave intervals
+    pub sampling: Sampling,
+    last_save: Instant,
     /// Reference time for converting DateTime to chart x-axis
     pub ref_time: Option<DateTime<Local>>,
 }
 
 impl App {
-    pub fn new(battery_name: String) -> Self {
-        let history = History::load();
-        let ref_time = history.samples.first().map(|s| s.timestamp);
+    pub fn new(source: &dyn BatterySource, config: Config) -> Self {
+        // Replayed and simulated data must not end up in the user's history file
+        let persist = source.is_live();
+        // With a recorder running, follow its history instead of writing a second copy
+        let writer_lock = if persist {
+            WriterLock::try_acquire(&WriterLock::lock_path())
+        } else {
+            Ok(None)
+        };
+        let following = persist && matches!(writer_lock, Ok(None));
+        let (mut history, health_log) = if following {
+            (History::load_following(), HealthLog::load_following())
+        } else if persist {
+            (History::load(), HealthLog::load())
+        } else {
+            (History::new(), HealthLog::new())
+        };
+        history.rules = config.sessions;
+        history.sleep_gap =
+            chrono::Duration::from_std(config.sampling.sleep_gap()).unwrap_or(history.sleep_gap);
+        history.resume_sessions(Local::now());
+        // Rollups reach further back than the raw samples
+        let ref_time = history.first_timestamp(None);
+        let charge_curve = ChargeCurve::learn(history.sessions());
 
         App {
             view: View::Dashboard,
@@ -119,25 +236,174 @@ impl App {
             viewport: ChartViewport::new(),
             session_viewport: ChartViewport::new(),
             running: true,
-            battery_name,
+            battery_name: source.battery_name(),
+            batteries: source.battery_ids(),
+            persist,
+            writer_lock: writer_lock.ok().flatten(),
+            recorder_pid: following
+                .then(|| WriterLock::holder(&WriterLock::lock_path()))
+                .flatten(),
+            last_event: None,
+            selected_battery: None,
             last_sample: None,
+            health: Vec::new(),
+            health_log,
+            thresholds: None,
+            charge_curve,
+            sample_error: None,
+            save_error: None,
+            session_sort: SessionSort::Date,
+            session_sort_ascending: false,
+            session_list_selected: 0,
+            session_list_kind: SessionKind::Charge,
             tick_count: 0,
+            sampling: config.sampling,
+            last_save: Instant::now(),
             ref_time,
         }
     }
 
+    /// Add the samples of one reading, or keep the error to show it
+    pub fn add_samples(&mut self, result: Result<Vec<BatterySample>, Error>) {
+        match result {
+            Ok(samples) => {
+                self.sample_error = None;
+                samples.into_iter().for_each(|s| self.add_sample(s));
+            }
+            Err(e) => self.sample_error = Some(e),
+        }
+    }
+
     /// Add a new battery sample
     pub fn add_sample(&mut self, sample: BatterySample) {
         if self.ref_time.is_none() {
             self.ref_time = Some(sample.timestamp);
         }
+        if sample.battery.is_some() {
+            self.history.add_sample(sample);
+            return;
+        }
         self.last_sample = Some(sample.clone());
         self.history.add_sample(sample);
         self.tick_count += 1;
+        self.learn_charge_curve();
+
+        if self.last_save.elapsed() >= self.sampling.save_interval() {
+            self.save();
+        }
+    }
+
+    /// Save history to disk, unless the data comes from a replay or simulation
+    /// or a recorder is writing it
+    pub fn save(&mut self) {
+        if self.persist {
+            self.save_error = self.history.save().err();
+        }
+        self.last_save = Instant::now();
+    }
+
+    /// How long to wait after the latest sample before taking the next
+    pub fn sample_interval(&self) -> Duration {
+        self.sampling.interval_after(self.last_sample.as_ref())
+    }
+
+    /// While following a recorder, pick up what it saved; once it has
+    /// stopped, take over writing the history
+    pub fn sync_with_recorder(&mut self) {
+        if !self.history.is_following() {
+            return;
+        }
+        if let Ok(Some(lock)) = WriterLock::try_acquire(&WriterLock::lock_path()) {
+            self.writer_lock = Some(lock);
+            self.recorder_pid = None;
+            self.history.take_over();
+            self.health_log.take_over();
+        } else {
+            self.history.refresh();
+        }
+        self.health_log.reload();
+        self.learn_charge_curve();
+    }
+
+    /// Relearn the charge curve once another charge session has finished
+    fn learn_charge_curve(&mut self) {
+        let sessions = self.history.sessions();
+        if sessions.len() != self.charge_curve.sessions {
+            self.charge_curve = ChargeCurve::learn(sessions);
+        }
+    }
+
+    /// Smoothed time to empty or to full for the selected battery, from
+    /// recent power and, while charging, the learned charge curve. Charging
+    /// stops at the firmware threshold if one is set.
+    pub fn remaining(&self) -> Option<Remaining> {
+        self.current_sample()?;
+        let target = estimate::charge_target(self.thresholds.as_ref());
+        estimate::remaining(self.selected_samples(), target, &self.charge_curve)
+    }
+
+    /// Note session changes reported by the recorder. It saves whenever a
+    /// session ends, so the stored session is picked up right away.
+    pub fn add_session_events(&mut self, events: Vec<SessionEvent>) {
+        if events.iter().any(|e| e.change == SessionChange::Ended) {
+            self.history.refresh();
+        }
+        if let Some(event) = events.into_iter().last() {
+            self.last_event = Some(event);
+        }
+    }
+
+    /// Store a fresh health reading and add it to the daily log
+    pub fn update_health(&mut self, readings: Vec<BatteryHealth>) {
+        if self.health_log.record(&readings) && self.persist && !self.history.is_following() {
+            self.save_error = self.health_log.save().err();
+        }
+        self.health = readings;
+    }
+
+    /// Store the current charge thresholds; the stop threshold also decides
+    /// when a charge session counts as completed
+    pub fn update_thresholds(&mut self, thresholds: Option<ChargeThresholds>) {
+        self.history.charge_end_threshold = thresholds.as_ref().and_then(|t| t.end);
+        self.thresholds = thresholds;
+    }
+
+    /// Samples for the selected battery (or the combined pack)
+    pub fn selected_samples(&self) -> &[BatterySample] {
+        self.history.samples_for(self.selected_battery.as_deref())
+    }
+
+    /// Latest live sample for the selected battery
+    pub fn current_sample(&self) -> Option<&BatterySample> {
+        self.last_sample.as_ref()?;
+        self.selected_samples().last()
+    }
+
+    /// Label for the current battery selection
+    pub fn selection_label(&self) -> &str {
+        match &self.selected_battery {
+            Some(name) => name,
+            None if self.batteries.is_empty() => &self.battery_name,
+            None => "Combined",
+        }
+    }
 
-        // Auto-save every 60 ticks (~5 min at 5s interval)
-        if self.tick_count.is_multiple_of(60) {
-            self.history.save();
+    /// Cycle through combined pack → each battery → combined pack
+    pub fn cycle_battery(&mut self) {
+        if self.batteries.is_empty() {
+            return;
+        }
+        self.selected_battery = match &self.selected_battery {
+            None => self.batteries.first().cloned(),
+            Some(current) => self
+                .batteries
+                .iter()
+                .position(|b| b == current)
+                .and_then(|i| self.batteries.get(i + 1))
+                .cloned(),
+        };
+        if self.view == View::HistoryChart {
+            self.fit_viewport();
         }
     }
 
@@ -155,40 +421,99 @@ impl App {
             .map(|rt| rt + chrono::Duration::milliseconds((x * 1000.0) as i64))
     }
 
-    /// Get chart data points for capacity (filtered by viewport)
-    pub fn capacity_chart_data(&self, samples: &[BatterySample]) -> Vec<(f64, f64)> {
+    /// Resolution the history chart is drawn at for the current zoom and pan
+    pub fn history_resolution(&self) -> Resolution {
         let (t_start, t_end) = self.viewport.visible_range();
-        samples
+        match self.x_to_time(t_start) {
+            Some(from) => {
+                self.history
+                    .resolution_for(self.selected_battery.as_deref(), from, t_end - t_start)
+            }
+            None => Resolution::Raw,
+        }
+    }
+
+    /// Get chart lines for capacity (filtered by viewport)
+    pub fn capacity_chart_data(&self) -> ChartLines {
+        self.history_lines(|s| s.capacity, |r| r.capacity)
+    }
+
+    /// Get chart lines for power (filtered by viewport)
+    pub fn power_chart_data(&self) -> ChartLines {
+        self.history_lines(|s| s.power_watts, |r| r.power_watts)
+    }
+
+    /// One value of the selected series within the viewport, at the
+    /// resolution picked by `history_resolution`
+    fn history_lines(
+        &self,
+        value: impl Fn(&BatterySample) -> f64,
+        stat: impl Fn(&Rollup) -> Stat,
+    ) -> ChartLines {
+        let (t_start, t_end) = self.viewport.visible_range();
+        let visible = |x: f64| x >= t_start && x <= t_end;
+        let mut lines = ChartLines::default();
+        match self.history_resolution() {
+            Resolution::Raw => {
+                lines.values = self
+                    .selected_samples()
+                    .iter()
+                    .map(|s| (self.time_to_x(&s.timestamp), value(s)))
+                    .filter(|(x, _)| visible(*x))
+                    .collect();
+            }
+            resolution => {
+                let buckets = self
+                    .history
+                    .rollups_for(resolution, self.selected_battery.as_deref());
+                for bucket in buckets {
+                    let x = self.time_to_x(&bucket.start);
+                    if visible(x) {
+                        let stat = stat(bucket);
+                        lines.values.push((x, stat.avg));
+                        lines.min.push((x, stat.min));
+                        lines.max.push((x, stat.max));
+                    }
+                }
+            }
+        }
+        lines
+    }
+
+    /// Chart x range of every recorded sleep
+    pub fn sleep_spans(&self) -> Vec<(f64, f64)> {
+        self.history
+            .sleeps
             .iter()
-            .map(|s| (self.time_to_x(&s.timestamp), s.capacity))
-            .filter(|(x, _)| *x >= t_start && *x <= t_end)
+            .map(|s| (self.time_to_x(&s.start), self.time_to_x(&s.end)))
             .collect()
     }
 
-    /// Get chart data points for power (filtered by viewport)
-    pub fn power_chart_data(&self, samples: &[BatterySample]) -> Vec<(f64, f64)> {
+    /// Get chart data points for temperature (filtered by viewport)
+    pub fn temperature_chart_data(&self, samples: &[BatterySample]) -> Vec<(f64, f64)> {
         let (t_start, t_end) = self.viewport.visible_range();
         samples
             .iter()
-            .map(|s| (self.time_to_x(&s.timestamp), s.power_watts))
+            .filter_map(|s| Some((self.time_to_x(&s.timestamp), s.temperature_c?)))
             .filter(|(x, _)| *x >= t_start && *x <= t_end)
             .collect()
     }
 
-    /// Update viewport to fit current data
+    /// Update viewport to fit current data, rollups included
     pub fn fit_viewport(&mut self) {
-        if let (Some(first), Some(last)) =
-            (self.history.samples.first(), self.history.samples.last())
-        {
-            let total = self.time_to_x(&last.timestamp) - self.time_to_x(&first.timestamp);
+        if let Some(last) = self.selected_samples().last() {
+            let total = self.time_to_x(&last.timestamp);
             self.viewport.fit_data(total);
         }
     }
 
     /// Update session viewport to fit session data
-    pub fn fit_session_viewport(&mut self, session_idx: usize) {
-        if let Some(session) = self.history.completed_sessions().get(session_idx)
-            && let (Some(first), Some(last)) = (session.samples.first(), session.samples.last())
+    pub fn fit_session_viewport(&mut self, kind: SessionKind, start: DateTime<Local>) {
+        if let Some(session) = self
+            .history
+            .session_index(kind, start)
+            .and_then(|idx| self.history.session(kind, idx))
+            && let (Some(first), Some(last)) = (session.samples().first(), session.samples().last())
         {
             let total = self.time_to_x(&last.timestamp) - self.time_to_x(&first.timestamp);
             self.session_viewport.fit_data(total);
@@ -198,7 +523,7 @@ impl App {
     /// Get the active viewport for current view
     pub fn active_viewport_mut(&mut self) -> &mut ChartViewport {
         match self.view {
-            View::SessionDetail(_) => &mut self.session_viewport,
+            View::SessionDetail(..) => &mut self.session_viewport,
             _ => &mut self.viewport,
         }
     }
@@ -212,10 +537,81 @@ impl App {
         self.fit_viewport();
     }
 
-    pub fn switch_to_session(&mut self, idx: usize) {
-        if idx < self.history.completed_sessions().len() {
-            self.view = View::SessionDetail(idx);
-            self.fit_session_viewport(idx);
+    pub fn switch_to_health(&mut self) {
+        self.view = View::Health;
+    }
+
+    pub fn switch_to_session_list(&mut self) {
+        self.view = View::SessionList;
+        let count = self.history.session_count(self.session_list_kind);
+        self.session_list_selected = self.session_list_selected.min(count.saturating_sub(1));
+    }
+
+    /// Session indices (into the listed kind's sessions) in list order
+    pub fn sorted_session_indices(&self) -> Vec<usize> {
+        let kind = self.session_list_kind;
+        let key = |idx: usize| {
+            self.history
+                .session(kind, idx)
+                .map(|s| self.session_sort.key(s))
+                .unwrap_or(0.0)
+        };
+        let mut indices: Vec<usize> = (0..self.history.session_count(kind)).collect();
+        indices.sort_by(|&a, &b| {
+            let ord = key(a).total_cmp(&key(b));
+            if self.session_sort_ascending {
+                ord
+            } else {
+                ord.reverse()
+            }
+        });
+        indices
+    }
+
+    pub fn session_list_up(&mut self) {
+        self.session_list_selected = self.session_list_selected.saturating_sub(1);
+    }
+
+    pub fn session_list_down(&mut self) {
+        let count = self.history.session_count(self.session_list_kind);
+        if self.session_list_selected + 1 < count {
+            self.session_list_selected += 1;
+        }
+    }
+
+    pub fn cycle_session_sort(&mut self) {
+        self.session_sort = self.session_sort.next();
+        self.session_list_selected = 0;
+    }
+
+    pub fn reverse_session_sort(&mut self) {
+        self.session_sort_ascending = !self.session_sort_ascending;
+        self.session_list_selected = 0;
+    }
+
+    /// Switch the session list between charge and discharge sessions
+    pub fn toggle_session_kind(&mut self) {
+        self.session_list_kind = match self.session_list_kind {
+            SessionKind::Charge => SessionKind::Discharge,
+            SessionKind::Discharge => SessionKind::Charge,
+        };
+        self.session_list_selected = 0;
+    }
+
+    /// Open the detail view for the highlighted session
+    pub fn open_selected_session(&mut self) {
+        if let Some(&idx) = self
+            .sorted_session_indices()
+            .get(self.session_list_selected)
+        {
+            self.switch_to_session(self.session_list_kind, idx);
+        }
+    }
+
+    pub fn switch_to_session(&mut self, kind: SessionKind, idx: usize) {
+        if let Some(start) = self.history.session(kind, idx).map(|s| s.start_time()) {
+            self.view = View::SessionDetail(kind, start);
+            self.fit_session_viewport(kind, start);
         }
     }
 }
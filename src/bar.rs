@@ -0,0 +1,261 @@
+use serde_json::json;
+
+use crate::battery::BatteryStatus;
+use crate::cli::BarFormat;
+use crate::format::{format_duration, format_remaining};
+use crate::report::Status;
+
+/// i3blocks marks a block urgent when its command exits with this status
+pub const I3BLOCKS_URGENT: i32 = 33;
+
+const GREEN: &str = "#8ec07c";
+const BLUE: &str = "#83a598";
+const YELLOW: &str = "#fabd2f";
+const RED: &str = "#fb4934";
+
+/// Capacity (%) below which a discharging battery counts as low
+#[derive(Debug, Clone, Copy)]
+pub struct Levels {
+    pub warning: f64,
+    pub critical: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Urgency {
+    Normal,
+    Warning,
+    Critical,
+}
+
+/// What one bar update shows, before it is put in a bar's format
+struct BarState {
+    text: String,
+    short_text: String,
+    tooltip: String,
+    /// CSS classes for waybar: the status, then `warning`/`critical` and
+    /// `limited` when they apply
+    classes: Vec<&'static str>,
+    color: Option<&'static str>,
+    percentage: f64,
+    urgency: Urgency,
+}
+
+impl BarState {
+    fn new(status: &Status, levels: Levels) -> Self {
+        let pack = &status.pack;
+        let discharging = pack.status == BatteryStatus::Discharging;
+        let urgency = match pack.capacity {
+            c if discharging && c < levels.critical => Urgency::Critical,
+            c if discharging && c < levels.warning => Urgency::Warning,
+            _ => Urgency::Normal,
+        };
+        // Held below full by the stop threshold rather than by a fault
+        let limited = pack.status == BatteryStatus::NotCharging
+            && status.thresholds.as_ref().is_some_and(|t| t.end.is_some());
+
+        let mut classes = vec![match pack.status {
+            BatteryStatus::Charging => "charging",
+            BatteryStatus::Discharging => "discharging",
+            BatteryStatus::NotCharging => "not-charging",
+            BatteryStatus::Full => "full",
+            BatteryStatus::Unknown => "unknown",
+        }];
+        match urgency {
+            Urgency::Critical => classes.push("critical"),
+            Urgency::Warning => classes.push("warning"),
+            Urgency::Normal => {}
+        }
+        if limited {
+            classes.push("limited");
+        }
+        let color = match urgency {
+            Urgency::Critical => Some(RED),
+            Urgency::Warning => Some(YELLOW),
+            Urgency::Normal if pack.status == BatteryStatus::Charging => Some(GREEN),
+            Urgency::Normal if pack.status == BatteryStatus::Full || limited => Some(BLUE),
+            Urgency::Normal => None,
+        };
+
+        let mut text = format!("{:.0}%", pack.capacity);
+        if pack.power_watts.abs() >= 0.01 {
+            text.push_str(&format!(" {:+.1} W", pack.power_watts));
+        }
+        let remaining = status.time_to_empty_secs.or(status.time_to_full_secs);
+        if let Some(secs) = remaining {
+            text.push_str(&format!(" {}", format_duration(secs)));
+        }
+
+        let mut tooltip = format!(
+            "{}: {:.1}% {}\nPower: {:+.2} W",
+            status.battery_name, pack.capacity, pack.status, pack.power_watts
+        );
+        if let Some(remaining) = status.remaining {
+            tooltip.push_str(&format!("\nLeft: {}", format_remaining(remaining)));
+        }
+        tooltip.push_str(&format!(
+            "\nEnergy: {:.2} / {:.2} Wh",
+            pack.energy_now_wh, pack.energy_full_wh
+        ));
+        if let Some(thresholds) = &status.thresholds {
+            tooltip.push_str(&format!("\nLimits: {thresholds}"));
+        }
+
+        BarState {
+            short_text: format!("{:.0}%", pack.capacity),
+            text,
+            tooltip,
+            classes,
+            color,
+            percentage: pack.capacity,
+            urgency,
+        }
+    }
+}
+
+/// Whether i3blocks should mark the block urgent
+pub fn is_critical(status: &Status, levels: Levels) -> bool {
+    BarState::new(status, levels).urgency == Urgency::Critical
+}
+
+/// One update in `format`, ending in a newline. `stream` selects the
+/// one-line-per-update form i3blocks reads with `interval=persist` and
+/// `format=json`; the other bars use the same form either way.
+pub fn render(status: &Status, format: BarFormat, levels: Levels, stream: bool) -> String {
+    let state = BarState::new(status, levels);
+    match format {
+        BarFormat::Waybar => {
+            let line = json!({
+                "text": state.text,
+                "alt": state.classes[0],
+                "tooltip": state.tooltip,
+                "class": state.classes,
+                "percentage": state.percentage.round() as u8,
+            });
+            format!("{line}\n")
+        }
+        BarFormat::I3blocks if stream => {
+            let mut line = json!({
+                "full_text": state.text,
+                "short_text": state.short_text,
+                "urgent": state.urgency == Urgency::Critical,
+            });
+            if let Some(color) = state.color {
+                line["color"] = color.into();
+            }
+            format!("{line}\n")
+        }
+        BarFormat::I3blocks => format!(
+            "{}\n{}\n{}\n",
+            state.text,
+            state.short_text,
+            state.color.unwrap_or_default()
+        ),
+        BarFormat::Polybar => match state.color {
+            Some(color) => format!("%{{F{color}}}{}%{{F-}}\n", state.text),
+            None => format!("{}\n", state.text),
+        },
+    }
+}
+
+/// An update saying the batteries cannot be read, with the reason where
+/// the bar has room for it
+pub fn render_error(error: &str, format: BarFormat, stream: bool) -> String {
+    match format {
+        BarFormat::Waybar => {
+            let line = json!({
+                "text": "⚠",
+                "alt": "error",
+                "tooltip": error,
+                "class": ["error"],
+            });
+            format!("{line}\n")
+        }
+        BarFormat::I3blocks if stream => {
+            let line = json!({ "full_text": "⚠ battery", "color": RED });
+            format!("{line}\n")
+        }
+        BarFormat::I3blocks => format!("⚠ battery\n⚠\n{RED}\n"),
+        BarFormat::Polybar => format!("%{{F{RED}}}⚠ battery%{{F-}}\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::ChargeThresholds;
+    use crate::estimate::Remaining;
+    use crate::test_support;
+
+    const LEVELS: Levels = Levels {
+        warning: 30.0,
+        critical: 10.0,
+    };
+
+    fn status(capacity: f64, power_watts: f64, status: BatteryStatus) -> Status {
+        let remaining = (status == BatteryStatus::Discharging).then_some(Remaining::Empty(5400.0));
+        Status {
+            battery_name: "BAT0".to_string(),
+            pack: test_support::sample(0, capacity, power_watts, status),
+            batteries: Vec::new(),
+            remaining,
+            time_to_empty_secs: remaining.map(|_| 5400.0),
+            time_to_full_secs: None,
+            thresholds: Some(ChargeThresholds {
+                start: Some(40),
+                end: Some(80),
+                behaviour: None,
+            }),
+            health: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn waybar_classes_follow_status_and_levels() {
+        let line = render(
+            &status(25.0, -8.04, BatteryStatus::Discharging),
+            BarFormat::Waybar,
+            LEVELS,
+            false,
+        );
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["text"], "25% -8.0 W 1h 30m");
+        assert_eq!(value["class"], json!(["discharging", "warning"]));
+        assert_eq!(value["percentage"], 25);
+
+        let line = render(
+            &status(80.0, 0.0, BatteryStatus::NotCharging),
+            BarFormat::Waybar,
+            LEVELS,
+            true,
+        );
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["text"], "80%");
+        assert_eq!(value["class"], json!(["not-charging", "limited"]));
+    }
+
+    #[test]
+    fn i3blocks_and_polybar_use_colors() {
+        let low = status(8.0, -8.0, BatteryStatus::Discharging);
+        assert!(is_critical(&low, LEVELS));
+        // The levels are exclusive: at 10% the battery is only low
+        assert!(!is_critical(
+            &status(10.0, -8.0, BatteryStatus::Discharging),
+            LEVELS
+        ));
+        assert_eq!(
+            render(&low, BarFormat::I3blocks, LEVELS, false),
+            format!("8% -8.0 W 1h 30m\n8%\n{RED}\n")
+        );
+        let streamed: serde_json::Value =
+            serde_json::from_str(&render(&low, BarFormat::I3blocks, LEVELS, true)).unwrap();
+        assert_eq!(streamed["urgent"], true);
+        assert_eq!(streamed["color"], RED);
+
+        let charging = status(50.0, 30.0, BatteryStatus::Charging);
+        assert!(!is_critical(&charging, LEVELS));
+        assert_eq!(
+            render(&charging, BarFormat::Polybar, LEVELS, true),
+            format!("%{{F{GREEN}}}50% +30.0 W%{{F-}}\n")
+        );
+    }
+}
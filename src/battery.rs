@@ -1,10 +1,14 @@
 use std::fmt;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
+use crate::error::Error;
+use crate::health::BatteryHealth;
+
 /// Battery charging state
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BatteryStatus {
@@ -27,67 +31,292 @@ impl fmt::Display for BatteryStatus {
     }
 }
 
+impl BatteryStatus {
+    /// Parse a sysfs `status` value (also accepts the variant names)
+    pub fn parse(s: &str) -> Self {
+        match s.trim() {
+            "Charging" => BatteryStatus::Charging,
+            "Discharging" => BatteryStatus::Discharging,
+            "Not charging" | "NotCharging" => BatteryStatus::NotCharging,
+            "Full" => BatteryStatus::Full,
+            _ => BatteryStatus::Unknown,
+        }
+    }
+}
+
+/// Which sysfs attribute family the power and energy figures came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnergySource {
+    /// `energy_now` / `energy_full` / `power_now` (µWh, µW)
+    #[default]
+    Energy,
+    /// `charge_now` / `charge_full` / `current_now` (µAh, µA), multiplied by voltage
+    Charge,
+}
+
+impl fmt::Display for EnergySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnergySource::Energy => write!(f, "energy_now"),
+            EnergySource::Charge => write!(f, "charge_now × voltage"),
+        }
+    }
+}
+
+/// An online external power supply (AC adapter or USB port) at sample time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdapterState {
+    /// Sysfs name, e.g. "AC" or "ucsi-source-psy-USBC000:001"
+    pub name: String,
+    /// Sysfs `type`: "Mains", "USB", ...
+    pub kind: String,
+    /// Active `usb_type` (the bracketed entry), e.g. "PD"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb_type: Option<String>,
+    /// Negotiated maximum voltage in volts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voltage_max_v: Option<f64>,
+    /// Negotiated maximum current in amps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_max_a: Option<f64>,
+}
+
+impl AdapterState {
+    /// Negotiated power limit in watts, if the supply reports voltage and current
+    pub fn max_watts(&self) -> Option<f64> {
+        Some(self.voltage_max_v? * self.current_max_a?).filter(|w| *w > 0.0)
+    }
+}
+
+impl fmt::Display for AdapterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.usb_type.as_deref() {
+            Some("C") => "USB-C".to_string(),
+            Some("PD") | Some("PD_DRP") => "USB-C PD".to_string(),
+            Some("PD_PPS") => "USB-C PD PPS".to_string(),
+            Some(other) if other != "Unknown" => format!("USB {other}"),
+            _ if self.kind == "Mains" => "AC adapter".to_string(),
+            _ => self.kind.clone(),
+        };
+        match self.max_watts() {
+            Some(watts) => write!(f, "{label} {watts:.0} W"),
+            None => write!(f, "{label}"),
+        }
+    }
+}
+
+/// Short description of the most capable online adapter, e.g. "USB-C PD 65 W"
+pub fn describe_adapters(adapters: &[AdapterState]) -> Option<String> {
+    adapters
+        .iter()
+        .max_by(|a, b| {
+            let watts = |x: &AdapterState| x.max_watts().unwrap_or(0.0);
+            watts(a).total_cmp(&watts(b))
+        })
+        .map(|a| a.to_string())
+}
+
+/// Firmware charge limits from `charge_control_*_threshold` and `charge_behaviour`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChargeThresholds {
+    /// Charging starts again below this percentage
+    pub start: Option<u8>,
+    /// Charging stops at this percentage
+    pub end: Option<u8>,
+    /// Active `charge_behaviour`, e.g. "auto" or "inhibit-charge"
+    pub behaviour: Option<String>,
+}
+
+impl fmt::Display for ChargeThresholds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => write!(f, "start {start}% / stop {end}%")?,
+            (None, Some(end)) => write!(f, "stop {end}%")?,
+            (Some(start), None) => write!(f, "start {start}%")?,
+            (None, None) => write!(f, "no limits")?,
+        }
+        match self.behaviour.as_deref() {
+            Some(behaviour) if behaviour != "auto" => write!(f, " ({behaviour})"),
+            _ => Ok(()),
+        }
+    }
+}
+
 /// A single battery data sample
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatterySample {
     pub timestamp: DateTime<Local>,
+    /// Battery this sample came from (sysfs name, e.g. "BAT1").
+    /// `None` for the combined pack, which is the only sample on single-battery systems.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battery: Option<String>,
     pub capacity: f64,    // percent 0-100
     pub power_watts: f64, // watts (positive = charging, negative = discharging)
     pub status: BatteryStatus,
     pub energy_now_wh: f64,  // watt-hours
     pub energy_full_wh: f64, // watt-hours
     pub voltage_now_v: f64,  // volts
+    /// Attribute family used for power and energy
+    #[serde(default)]
+    pub source: EnergySource,
+    /// Battery temperature in °C, if the battery or its hwmon node reports one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_c: Option<f64>,
+    /// Online external power supplies (only on the pack sample)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adapters: Vec<AdapterState>,
 }
 
+/// Default location of the kernel's power supply class
+pub const SYSFS_POWER_SUPPLY: &str = "/sys/class/power_supply";
+
 /// Reader for Linux sysfs battery interface
 pub struct BatteryReader {
-    base_path: PathBuf,
+    batteries: Vec<PathBuf>,
+    /// Non-battery supplies (Mains, USB), checked for `online` every sample
+    adapters: Vec<PathBuf>,
 }
 
 impl BatteryReader {
-    pub fn new() -> Option<Self> {
-        // Try to find a battery in /sys/class/power_supply/
-        let ps_path = Path::new("/sys/class/power_supply");
-        if !ps_path.exists() {
-            return None;
-        }
-
-        for entry in fs::read_dir(ps_path).ok()? {
-            let entry = entry.ok()?;
-            let type_path = entry.path().join("type");
-            if let Ok(ptype) = fs::read_to_string(&type_path)
-                && ptype.trim() == "Battery"
-            {
-                return Some(BatteryReader {
-                    base_path: entry.path(),
-                });
+    pub fn new() -> Result<Self, Error> {
+        Self::with_root(SYSFS_POWER_SUPPLY)
+    }
+
+    /// Find every battery under `root`, a directory laid out like
+    /// `/sys/class/power_supply/` (one subdirectory per supply)
+    pub fn with_root(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let ps_path = root.as_ref();
+        let entries = fs::read_dir(ps_path).map_err(|source| Error::PowerSupplies {
+            root: ps_path.to_path_buf(),
+            source,
+        })?;
+
+        let mut batteries = Vec::new();
+        let mut adapters = Vec::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            // Peripheral batteries (mice, keyboards) report scope=Device
+            if Self::read_sysfs_string(&path, "scope").as_deref() == Some("Device") {
+                continue;
+            }
+            match Self::read_sysfs_string(&path, "type").as_deref() {
+                Some("Battery") => batteries.push(path),
+                Some(_) if path.join("online").exists() => adapters.push(path),
+                _ => {}
             }
         }
-        None
+
+        if batteries.is_empty() {
+            return Err(Error::NoBattery {
+                root: ps_path.to_path_buf(),
+            });
+        }
+        // read_dir order is arbitrary; keep BAT0 before BAT1
+        batteries.sort();
+        adapters.sort();
+        Ok(BatteryReader {
+            batteries,
+            adapters,
+        })
     }
 
-    fn read_sysfs_string(&self, filename: &str) -> Option<String> {
-        let path = self.base_path.join(filename);
+    fn read_sysfs_string(base_path: &Path, filename: &str) -> Option<String> {
+        let path = base_path.join(filename);
         fs::read_to_string(path).ok().map(|s| s.trim().to_string())
     }
 
-    fn read_sysfs_i64(&self, filename: &str) -> Option<i64> {
-        self.read_sysfs_string(filename)?.parse::<i64>().ok()
+    fn read_sysfs_i64(base_path: &Path, filename: &str) -> Option<i64> {
+        Self::read_sysfs_string(base_path, filename)?
+            .parse::<i64>()
+            .ok()
     }
 
-    pub fn sample(&self) -> Option<BatterySample> {
-        let capacity = self.read_sysfs_i64("capacity")? as f64;
-        let status = match self.read_sysfs_string("status")?.as_str() {
-            "Charging" => BatteryStatus::Charging,
-            "Discharging" => BatteryStatus::Discharging,
-            "Not charging" => BatteryStatus::NotCharging,
-            "Full" => BatteryStatus::Full,
-            _ => BatteryStatus::Unknown,
-        };
+    /// Read an attribute every battery must have, saying which one failed
+    fn require_sysfs_string(base_path: &Path, filename: &str) -> Result<String, Error> {
+        let path = base_path.join(filename);
+        match fs::read_to_string(&path) {
+            Ok(s) => Ok(s.trim().to_string()),
+            Err(source) => Err(Error::Attribute { path, source }),
+        }
+    }
+
+    fn require_sysfs_i64(base_path: &Path, filename: &str) -> Result<i64, Error> {
+        let value = Self::require_sysfs_string(base_path, filename)?;
+        value.parse().map_err(|_| Error::InvalidAttribute {
+            path: base_path.join(filename),
+            value,
+        })
+    }
+
+    /// Sysfs names of all batteries (e.g. "BAT0", "BAT1")
+    pub fn battery_ids(&self) -> Vec<String> {
+        self.batteries.iter().map(|p| dir_name(p)).collect()
+    }
+
+    /// Sample every battery.
+    ///
+    /// On single-battery systems this returns one untagged sample. With several
+    /// batteries it returns one sample per battery, tagged with its sysfs name,
+    /// followed by an untagged sample for the combined pack. A battery that
+    /// cannot be read is left out; it is an error only if none can be read.
+    pub fn sample(&self) -> Result<Vec<BatterySample>, Error> {
+        let timestamp = Local::now();
+        let mut samples = Vec::new();
+        let mut first_error = None;
+        for path in &self.batteries {
+            match Self::sample_battery(path, timestamp) {
+                Ok(sample) => samples.push(sample),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if let (true, Some(e)) = (samples.is_empty(), first_error) {
+            return Err(e);
+        }
+
+        if self.batteries.len() > 1 && !samples.is_empty() {
+            let pack = combine_samples(&samples);
+            samples.push(pack);
+        } else if let Some(only) = samples.first_mut() {
+            only.battery = None;
+        }
+        if let Some(pack) = samples.last_mut() {
+            pack.adapters = self.online_adapters();
+        }
+        Ok(samples)
+    }
+
+    /// Read every external power supply that is currently online
+    pub fn online_adapters(&self) -> Vec<AdapterState> {
+        self.adapters
+            .iter()
+            .filter(|path| Self::read_sysfs_i64(path, "online").unwrap_or(0) > 0)
+            .map(|path| AdapterState {
+                name: dir_name(path),
+                kind: Self::read_sysfs_string(path, "type").unwrap_or_default(),
+                // usb_type lists all types with the active one in brackets: "C [PD] PD_PPS"
+                usb_type: Self::read_sysfs_string(path, "usb_type").and_then(|types| {
+                    types
+                        .split_whitespace()
+                        .find_map(|t| t.strip_prefix('[')?.strip_suffix(']'))
+                        .map(str::to_string)
+                }),
+                voltage_max_v: Self::read_sysfs_i64(path, "voltage_max")
+                    .map(|uv| uv as f64 / 1_000_000.0),
+                current_max_a: Self::read_sysfs_i64(path, "current_max")
+                    .map(|ua| ua as f64 / 1_000_000.0),
+            })
+            .collect()
+    }
+
+    fn sample_battery(path: &Path, timestamp: DateTime<Local>) -> Result<BatterySample, Error> {
+        let capacity = Self::require_sysfs_i64(path, "capacity")? as f64;
+        let status = BatteryStatus::parse(&Self::require_sysfs_string(path, "status")?);
 
-        // power_now is in microwatts
-        let power_uw = self.read_sysfs_i64("power_now").unwrap_or(0);
-        let power_watts = power_uw as f64 / 1_000_000.0;
+        let voltage_uv = Self::read_sysfs_i64(path, "voltage_now").unwrap_or(0);
+        let (source, power_watts, energy_now_wh, energy_full_wh) =
+            Self::read_power_and_energy(path, voltage_uv);
 
         // Sign convention: positive = charging, negative = discharging
         let signed_power = match status {
@@ -96,31 +325,431 @@ impl BatteryReader {
             _ => 0.0,
         };
 
-        let energy_now_uh = self.read_sysfs_i64("energy_now").unwrap_or(0);
-        let energy_full_uh = self.read_sysfs_i64("energy_full").unwrap_or(0);
-        let voltage_uv = self.read_sysfs_i64("voltage_now").unwrap_or(0);
-
-        Some(BatterySample {
-            timestamp: Local::now(),
+        Ok(BatterySample {
+            timestamp,
+            battery: Some(dir_name(path)),
             capacity,
             power_watts: signed_power,
             status,
-            energy_now_wh: energy_now_uh as f64 / 1_000_000.0,
-            energy_full_wh: energy_full_uh as f64 / 1_000_000.0,
+            energy_now_wh,
+            energy_full_wh,
             voltage_now_v: voltage_uv as f64 / 1_000_000.0,
+            source,
+            temperature_c: Self::read_temperature(path),
+            adapters: Vec::new(),
+        })
+    }
+
+    /// Battery temperature in °C.
+    ///
+    /// Prefers the power_supply `temp` attribute (tenths of °C) and falls back to a
+    /// hwmon node registered for the battery (`temp1_input`, millidegrees).
+    fn read_temperature(path: &Path) -> Option<f64> {
+        if let Some(tenths) = Self::read_sysfs_i64(path, "temp") {
+            return Some(tenths as f64 / 10.0);
+        }
+
+        // hwmon nodes live under BAT0/hwmonN/ or BAT0/device/hwmon/hwmonN/
+        [path.to_path_buf(), path.join("device/hwmon")]
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.filter_map(|e| e.ok()))
+            .map(|entry| entry.path())
+            .filter(|p| {
+                p.file_name()
+                    .is_some_and(|n| n.to_string_lossy().starts_with("hwmon"))
+            })
+            .find_map(|hwmon| Self::read_sysfs_i64(&hwmon, "temp1_input"))
+            .map(|milli| milli as f64 / 1000.0)
+    }
+
+    /// Read power (W, unsigned) and energy (Wh) from whichever attribute family exists.
+    ///
+    /// Batteries exposing only `charge_*`/`current_now` report µAh and µA, so watts and
+    /// watt-hours are derived by multiplying with the voltage.
+    fn read_power_and_energy(path: &Path, voltage_uv: i64) -> (EnergySource, f64, f64, f64) {
+        let has_energy = path.join("energy_now").exists();
+        let has_charge = path.join("charge_now").exists();
+
+        if has_energy || !has_charge {
+            // power_now is in microwatts, energy_* in microwatt-hours
+            let power_uw = Self::read_sysfs_i64(path, "power_now").unwrap_or(0);
+            let energy_now_uh = Self::read_sysfs_i64(path, "energy_now").unwrap_or(0);
+            let energy_full_uh = Self::read_sysfs_i64(path, "energy_full").unwrap_or(0);
+            return (
+                EnergySource::Energy,
+                power_uw.unsigned_abs() as f64 / 1_000_000.0,
+                energy_now_uh as f64 / 1_000_000.0,
+                energy_full_uh as f64 / 1_000_000.0,
+            );
+        }
+
+        // current_now is in microamps, charge_* in microamp-hours.
+        // Some drivers report a negative current while discharging; the sign is
+        // taken from status instead.
+        let current_ua = Self::read_sysfs_i64(path, "current_now").unwrap_or(0);
+        let charge_now_uah = Self::read_sysfs_i64(path, "charge_now").unwrap_or(0);
+        let charge_full_uah = Self::read_sysfs_i64(path, "charge_full").unwrap_or(0);
+
+        // Capacity in Wh is conventionally rated at the minimum design voltage
+        let design_uv = Self::read_sysfs_i64(path, "voltage_min_design")
+            .filter(|v| *v > 0)
+            .unwrap_or(voltage_uv);
+
+        let volts = voltage_uv as f64 / 1_000_000.0;
+        let design_volts = design_uv as f64 / 1_000_000.0;
+        (
+            EnergySource::Charge,
+            current_ua.unsigned_abs() as f64 / 1_000_000.0 * volts,
+            charge_now_uah as f64 / 1_000_000.0 * design_volts,
+            charge_full_uah as f64 / 1_000_000.0 * design_volts,
+        )
+    }
+
+    /// Read cycle count, design capacity and identity of every battery
+    pub fn health(&self) -> Vec<BatteryHealth> {
+        self.batteries
+            .iter()
+            .map(|path| {
+                let voltage_uv = Self::read_sysfs_i64(path, "voltage_now").unwrap_or(0);
+                let (source, _, _, energy_full_wh) = Self::read_power_and_energy(path, voltage_uv);
+                let energy_full_design_wh = match source {
+                    EnergySource::Energy => {
+                        Self::read_sysfs_i64(path, "energy_full_design").unwrap_or(0) as f64
+                            / 1_000_000.0
+                    }
+                    EnergySource::Charge => {
+                        // Same conversion as charge_full in read_power_and_energy
+                        let design_uv = Self::read_sysfs_i64(path, "voltage_min_design")
+                            .filter(|v| *v > 0)
+                            .unwrap_or(voltage_uv);
+                        Self::read_sysfs_i64(path, "charge_full_design").unwrap_or(0) as f64
+                            / 1_000_000.0
+                            * (design_uv as f64 / 1_000_000.0)
+                    }
+                };
+                BatteryHealth {
+                    battery: dir_name(path),
+                    // Many firmwares report 0 when they do not count cycles
+                    cycle_count: Self::read_sysfs_i64(path, "cycle_count")
+                        .filter(|c| *c > 0)
+                        .map(|c| c as u32),
+                    energy_full_wh,
+                    energy_full_design_wh,
+                    technology: Self::read_sysfs_string(path, "technology")
+                        .filter(|s| !s.is_empty()),
+                    serial_number: Self::read_sysfs_string(path, "serial_number")
+                        .filter(|s| !s.is_empty()),
+                }
+            })
+            .collect()
+    }
+
+    /// Charge thresholds of the first battery that supports them
+    pub fn thresholds(&self) -> Option<ChargeThresholds> {
+        self.batteries.iter().find_map(|path| {
+            let percent = |name| {
+                Self::read_sysfs_i64(path, name)
+                    .and_then(|v| u8::try_from(v).ok())
+                    .filter(|v| *v <= 100)
+            };
+            let thresholds = ChargeThresholds {
+                start: percent("charge_control_start_threshold"),
+                end: percent("charge_control_end_threshold"),
+                // charge_behaviour lists all options with the active one in brackets
+                behaviour: Self::read_sysfs_string(path, "charge_behaviour").and_then(|v| {
+                    v.split_whitespace()
+                        .find_map(|t| t.strip_prefix('[')?.strip_suffix(']'))
+                        .map(str::to_string)
+                }),
+            };
+            (thresholds != ChargeThresholds::default()).then_some(thresholds)
         })
     }
 
+    /// Write start/end charge thresholds to every battery that supports them.
+    /// Returns the names of the batteries that were updated.
+    pub fn set_thresholds(&self, start: u8, end: u8) -> Result<Vec<String>, Error> {
+        if start >= end || end > 100 {
+            return Err(Error::InvalidThresholds { start, end });
+        }
+
+        let mut updated = Vec::new();
+        for path in &self.batteries {
+            let start_path = path.join("charge_control_start_threshold");
+            let end_path = path.join("charge_control_end_threshold");
+            if !end_path.exists() {
+                continue;
+            }
+
+            // The kernel rejects a start above the current end (and vice versa),
+            // so raise the end first when moving the window up
+            let current_end = Self::read_sysfs_i64(path, "charge_control_end_threshold");
+            let mut writes = vec![(&start_path, start), (&end_path, end)];
+            if current_end.is_some_and(|e| i64::from(start) >= e) {
+                writes.reverse();
+            }
+            for (attr, value) in writes {
+                if !attr.exists() {
+                    continue;
+                }
+                fs::write(attr, format!("{value}\n")).map_err(|source| Error::AttributeWrite {
+                    path: attr.to_path_buf(),
+                    source,
+                })?;
+            }
+            updated.push(dir_name(path));
+        }
+
+        if updated.is_empty() {
+            return Err(Error::Attribute {
+                path: self.batteries[0].join("charge_control_end_threshold"),
+                source: io::ErrorKind::NotFound.into(),
+            });
+        }
+        Ok(updated)
+    }
+
+    /// Display name for the whole pack, e.g. "SMP 5B10W13930" or "BAT0 + BAT1"
     pub fn battery_name(&self) -> String {
-        let model = self.read_sysfs_string("model_name").unwrap_or_default();
-        let mfr = self.read_sysfs_string("manufacturer").unwrap_or_default();
+        if self.batteries.len() > 1 {
+            return self.battery_ids().join(" + ");
+        }
+        let base_path = &self.batteries[0];
+        let model = Self::read_sysfs_string(base_path, "model_name").unwrap_or_default();
+        let mfr = Self::read_sysfs_string(base_path, "manufacturer").unwrap_or_default();
         if model.is_empty() && mfr.is_empty() {
-            self.base_path
-                .file_name()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_else(|| "Battery".to_string())
+            dir_name(base_path)
         } else {
             format!("{mfr} {model}").trim().to_string()
         }
     }
 }
+
+fn dir_name(path: &Path) -> String {
+    path.file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Battery".to_string())
+}
+
+/// Merge per-battery samples into one virtual pack sample
+fn combine_samples(samples: &[BatterySample]) -> BatterySample {
+    let energy_now_wh: f64 = samples.iter().map(|s| s.energy_now_wh).sum();
+    let energy_full_wh: f64 = samples.iter().map(|s| s.energy_full_wh).sum();
+    let count = samples.len() as f64;
+
+    // Weight capacity by energy when available, otherwise plain average
+    let capacity = if energy_full_wh > 0.0 {
+        energy_now_wh / energy_full_wh * 100.0
+    } else {
+        samples.iter().map(|s| s.capacity).sum::<f64>() / count
+    };
+
+    let any = |status: BatteryStatus| samples.iter().any(|s| s.status == status);
+    let status = if any(BatteryStatus::Charging) {
+        BatteryStatus::Charging
+    } else if any(BatteryStatus::Discharging) {
+        BatteryStatus::Discharging
+    } else if samples.iter().all(|s| s.status == BatteryStatus::Full) {
+        BatteryStatus::Full
+    } else if any(BatteryStatus::NotCharging) {
+        BatteryStatus::NotCharging
+    } else {
+        BatteryStatus::Unknown
+    };
+
+    BatterySample {
+        timestamp: samples[0].timestamp,
+        battery: None,
+        capacity,
+        power_watts: samples.iter().map(|s| s.power_watts).sum(),
+        status,
+        energy_now_wh,
+        energy_full_wh,
+        voltage_now_v: samples.iter().map(|s| s.voltage_now_v).sum::<f64>() / count,
+        source: if samples.iter().any(|s| s.source == EnergySource::Charge) {
+            EnergySource::Charge
+        } else {
+            EnergySource::Energy
+        },
+        // The hottest cell is what matters
+        temperature_c: samples
+            .iter()
+            .filter_map(|s| s.temperature_c)
+            .reduce(f64::max),
+        adapters: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn reader(name: &str) -> BatteryReader {
+        BatteryReader::with_root(fixture(name)).expect("fixture has a battery")
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn thinkpad_energy_family() {
+        let reader = reader("thinkpad");
+        assert_eq!(reader.battery_ids(), vec!["BAT0"]);
+        assert_eq!(reader.battery_name(), "SMP 5B10W13930");
+
+        let samples = reader.sample().unwrap();
+        assert_eq!(samples.len(), 1);
+        let sample = &samples[0];
+        assert_eq!(sample.battery, None);
+        assert_eq!(sample.status, BatteryStatus::Discharging);
+        assert_eq!(sample.source, EnergySource::Energy);
+        assert_close(sample.capacity, 62.0);
+        assert_close(sample.power_watts, -7.312);
+        assert_close(sample.energy_now_wh, 31.254);
+        assert_close(sample.energy_full_wh, 50.12);
+        assert_close(sample.voltage_now_v, 11.847);
+        // AC and the USB-C port are both offline
+        assert!(sample.adapters.is_empty());
+        assert_eq!(sample.temperature_c, None);
+    }
+
+    #[test]
+    fn dell_charge_family_is_converted_with_voltage() {
+        let reader = reader("dell");
+        assert_eq!(reader.battery_name(), "SMP DELL 40G8M03");
+
+        let samples = reader.sample().unwrap();
+        assert_eq!(samples.len(), 1);
+        let sample = &samples[0];
+        assert_eq!(sample.status, BatteryStatus::Charging);
+        assert_eq!(sample.source, EnergySource::Charge);
+        // 2.405 A × 12.612 V
+        assert_close(sample.power_watts, 2.405 * 12.612);
+        // µAh × voltage_min_design (11.4 V)
+        assert_close(sample.energy_now_wh, 1.812 * 11.4);
+        assert_close(sample.energy_full_wh, 4.213 * 11.4);
+        assert_close(sample.temperature_c.unwrap(), 31.2);
+
+        let names: Vec<&str> = sample.adapters.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["AC", "ucsi-source-psy-USBC000:001"]);
+        assert_eq!(sample.adapters[1].usb_type.as_deref(), Some("PD"));
+        assert_eq!(
+            describe_adapters(&sample.adapters).as_deref(),
+            Some("USB-C PD 65 W")
+        );
+    }
+
+    #[test]
+    fn surface_two_batteries_and_combined_pack() {
+        let reader = reader("surface");
+        assert_eq!(reader.battery_ids(), vec!["BAT1", "BAT2"]);
+        assert_eq!(reader.battery_name(), "BAT1 + BAT2");
+
+        let samples = reader.sample().unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0].battery.as_deref(), Some("BAT1"));
+        assert_eq!(samples[1].battery.as_deref(), Some("BAT2"));
+        // BAT1 reports through hwmon only, BAT2 through `temp`
+        assert_close(samples[0].temperature_c.unwrap(), 30.5);
+        assert_close(samples[1].temperature_c.unwrap(), 27.9);
+
+        let pack = &samples[2];
+        assert_eq!(pack.battery, None);
+        assert!(samples[0].adapters.is_empty() && pack.adapters.is_empty());
+        assert_eq!(pack.status, BatteryStatus::Discharging);
+        assert_close(pack.power_watts, -(4.12 + 6.31));
+        assert_close(pack.energy_now_wh, 9.372 + 34.272);
+        assert_close(pack.energy_full_wh, 17.04 + 48.96);
+        assert_close(pack.capacity, (9.372 + 34.272) / (17.04 + 48.96) * 100.0);
+        assert_close(pack.temperature_c.unwrap(), 30.5);
+    }
+
+    #[test]
+    fn health_from_design_capacity() {
+        let health = reader("thinkpad").health();
+        assert_eq!(health.len(), 1);
+        assert_eq!(health[0].cycle_count, Some(412));
+        assert_eq!(health[0].technology.as_deref(), Some("Li-poly"));
+        assert_eq!(health[0].serial_number.as_deref(), Some("1234"));
+        assert_close(health[0].health_percent().unwrap(), 50.12 / 57.0 * 100.0);
+
+        // charge_full_design × voltage_min_design; cycle_count 0 means unknown
+        let health = reader("dell").health();
+        assert_eq!(health[0].cycle_count, None);
+        assert_close(health[0].energy_full_design_wh, 4.667 * 11.4);
+        assert_close(
+            health[0].wear_percent().unwrap(),
+            (1.0 - 4.213 / 4.667) * 100.0,
+        );
+    }
+
+    #[test]
+    fn charge_thresholds() {
+        let thresholds = reader("thinkpad").thresholds().unwrap();
+        assert_eq!(thresholds.start, Some(75));
+        assert_eq!(thresholds.end, Some(80));
+        assert_eq!(thresholds.behaviour.as_deref(), Some("auto"));
+        assert_eq!(thresholds.to_string(), "start 75% / stop 80%");
+
+        assert_eq!(reader("dell").thresholds(), None);
+        let err = reader("dell").set_thresholds(40, 80).unwrap_err();
+        assert!(
+            matches!(&err, Error::Attribute { path, .. } if path.ends_with("BAT0/charge_control_end_threshold")),
+            "{err}"
+        );
+        assert!(matches!(
+            reader("thinkpad").set_thresholds(80, 40),
+            Err(Error::InvalidThresholds { start: 80, end: 40 })
+        ));
+    }
+
+    #[test]
+    fn desktop_has_no_system_battery() {
+        // Only a wireless mouse battery (scope=Device), which must be ignored
+        assert!(matches!(
+            BatteryReader::with_root(fixture("desktop")),
+            Err(Error::NoBattery { .. })
+        ));
+        assert!(matches!(
+            BatteryReader::with_root(fixture("does-not-exist")),
+            Err(Error::PowerSupplies { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_attribute_is_named() {
+        let root = crate::test_support::temp_dir("sysfs");
+        let bat = root.join("BAT0");
+        fs::create_dir_all(&bat).unwrap();
+        fs::write(bat.join("type"), "Battery\n").unwrap();
+        fs::write(bat.join("status"), "Discharging\n").unwrap();
+
+        let err = BatteryReader::with_root(&root)
+            .unwrap()
+            .sample()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{}/capacity is missing", bat.display())
+        );
+
+        fs::write(bat.join("capacity"), "lots\n").unwrap();
+        let err = BatteryReader::with_root(&root)
+            .unwrap()
+            .sample()
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidAttribute { value, .. } if value == "lots"));
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,288 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::rollup::Resolution;
+
+/// Battery history monitor: an interactive TUI by default, or one of the
+/// commands below
+#[derive(Debug, Parser)]
+#[command(name = "bathis", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub source: SourceArgs,
+
+    /// Read settings from FILE instead of ~/.config/bathis/config.toml
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Old spelling of `bathis record`, kept for existing service files
+    #[arg(long, hide = true)]
+    pub record: bool,
+}
+
+/// Where samples come from and how often they are taken
+#[derive(Debug, Args)]
+pub struct SourceArgs {
+    /// Read power supplies from DIR instead of /sys/class/power_supply
+    #[arg(long, value_name = "DIR", global = true)]
+    pub sysfs_root: Option<PathBuf>,
+
+    /// Play back a recorded history.db, history.json or CSV file
+    #[arg(long, value_name = "FILE", global = true)]
+    pub replay: Option<PathBuf>,
+
+    /// Simulate charge/discharge cycles instead of reading sysfs
+    #[arg(long, global = true)]
+    pub synthetic: bool,
+
+    /// Playback speed for --replay/--synthetic
+    #[arg(long, value_name = "N", default_value_t = 1.0, value_parser = positive_f64, global = true)]
+    pub speed: f64,
+
+    /// Seconds between samples [default: 5]
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..), global = true)]
+    pub interval: Option<u64>,
+
+    /// Sample faster while charging or draining hard, slower idle on AC
+    #[arg(long, global = true)]
+    pub adaptive: bool,
+}
+
+impl SourceArgs {
+    /// Whether a source other than the system's batteries was asked for
+    pub fn other_source(&self) -> bool {
+        self.sysfs_root.is_some() || self.replay.is_some() || self.synthetic
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the interactive monitor (the default)
+    Tui,
+    /// Print the current battery reading once
+    Status {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run headless, sampling the batteries to the history (a TUI started
+    /// later attaches to it)
+    Record(RecordArgs),
+    /// List past charge sessions
+    Sessions {
+        /// List discharge sessions instead
+        #[arg(long)]
+        discharge: bool,
+        /// Only the newest N sessions
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write the saved history to stdout as CSV or JSON
+    Export {
+        /// Raw samples or one of the rollup tiers
+        #[arg(long, value_enum, default_value_t = ExportResolution::Raw)]
+        resolution: ExportResolution,
+        /// Only this battery ("pack" for the combined pack)
+        #[arg(long, value_name = "NAME")]
+        battery: Option<String>,
+        /// Write JSON instead of CSV
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print session, sleep and health summaries of the saved history
+    Stats {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print capacity, power and time left for waybar, i3blocks or polybar
+    Bar {
+        #[arg(long, value_enum)]
+        format: BarFormat,
+        /// Keep running and print an update after every sample
+        #[arg(long)]
+        watch: bool,
+        /// Capacity (%) below which a discharging battery shows as a warning
+        #[arg(long, value_name = "PERCENT", default_value_t = 25.0, value_parser = percent)]
+        warning: f64,
+        /// Capacity (%) below which a discharging battery shows as critical
+        #[arg(long, value_name = "PERCENT", default_value_t = 10.0, value_parser = percent)]
+        critical: f64,
+    },
+    /// Show or set the battery charge thresholds
+    Threshold {
+        #[command(subcommand)]
+        action: Option<ThresholdAction>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Options of `bathis record`
+#[derive(Debug, Args)]
+pub struct RecordArgs {
+    /// Also print every sample as a line of JSON on stdout
+    #[arg(long)]
+    pub json: bool,
+    /// Serve Prometheus metrics at http://ADDR/metrics, e.g. 127.0.0.1:9101
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_listen: Option<String>,
+    /// Write Prometheus metrics to FILE after every sample, for
+    /// node_exporter's textfile collector
+    #[arg(long, value_name = "FILE")]
+    pub metrics_textfile: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+pub enum ThresholdAction {
+    /// Show the current thresholds (the default)
+    Show,
+    /// Start charging below START %, stop at END % (needs root)
+    Set { start: u8, end: u8 },
+}
+
+/// Output format of `bathis bar`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BarFormat {
+    /// JSON for a custom module with `"return-type": "json"`
+    Waybar,
+    /// full_text, short_text and color lines (JSON lines with --watch)
+    I3blocks,
+    /// Text with %{F} color tags
+    Polybar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportResolution {
+    Raw,
+    Minute,
+    QuarterHour,
+}
+
+impl From<ExportResolution> for Resolution {
+    fn from(resolution: ExportResolution) -> Self {
+        match resolution {
+            ExportResolution::Raw => Resolution::Raw,
+            ExportResolution::Minute => Resolution::Minute,
+            ExportResolution::QuarterHour => Resolution::QuarterHour,
+        }
+    }
+}
+
+impl Cli {
+    /// The command to run, taking `--record` and no command into account
+    pub fn selected_command(&self) -> &Command {
+        match &self.command {
+            Some(command) => command,
+            None if self.record => &Command::Record(RecordArgs {
+                json: false,
+                metrics_listen: None,
+                metrics_textfile: None,
+            }),
+            None => &Command::Tui,
+        }
+    }
+}
+
+fn positive_f64(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(v) if v > 0.0 => Ok(v),
+        _ => Err(format!("expected a number above 0, got {value:?}")),
+    }
+}
+
+fn percent(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(v) if (0.0..=100.0).contains(&v) => Ok(v),
+        _ => Err(format!(
+            "expected a percentage from 0 to 100, got {value:?}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("bathis").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn definition_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn commands_and_global_options_parse() {
+        assert!(matches!(parse(&[]).selected_command(), Command::Tui));
+        assert!(matches!(
+            parse(&["--record"]).selected_command(),
+            Command::Record(RecordArgs { json: false, .. })
+        ));
+        let cli = parse(&["record", "--metrics-listen", "127.0.0.1:9101"]);
+        assert!(matches!(
+            cli.selected_command(),
+            Command::Record(RecordArgs {
+                metrics_listen: Some(addr),
+                metrics_textfile: None,
+                ..
+            }) if addr == "127.0.0.1:9101"
+        ));
+
+        let cli = parse(&["sessions", "--discharge", "--limit", "5", "--json"]);
+        assert!(matches!(
+            cli.selected_command(),
+            Command::Sessions {
+                discharge: true,
+                limit: Some(5),
+                json: true
+            }
+        ));
+
+        let cli = parse(&["status", "--sysfs-root", "tests/fixtures/surface"]);
+        assert!(matches!(
+            cli.selected_command(),
+            Command::Status { json: false }
+        ));
+        assert!(cli.source.other_source());
+
+        let cli = parse(&["threshold", "set", "40", "80"]);
+        assert!(matches!(
+            cli.selected_command(),
+            Command::Threshold {
+                action: Some(ThresholdAction::Set { start: 40, end: 80 }),
+                ..
+            }
+        ));
+
+        let cli = parse(&["bar", "--format", "i3blocks", "--watch"]);
+        assert!(matches!(
+            cli.selected_command(),
+            Command::Bar {
+                format: BarFormat::I3blocks,
+                watch: true,
+                ..
+            }
+        ));
+        assert!(Cli::try_parse_from(["bathis", "bar"]).is_err());
+        for level in ["--warning", "--critical"] {
+            for value in ["150", "-1", "NaN"] {
+                let args = ["bathis", "bar", "--format", "waybar", level, value];
+                assert!(Cli::try_parse_from(args).is_err(), "{level} {value}");
+            }
+        }
+
+        assert!(Cli::try_parse_from(["bathis", "--speed", "0"]).is_err());
+        assert!(Cli::try_parse_from(["bathis", "record", "--interval", "0"]).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::battery::{BatterySample, BatteryStatus};
+
+/// User settings from `~/.config/bathis/config.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub sessions: SessionRules,
+    pub sampling: Sampling,
+}
+
+/// When a charge session counts as completed rather than partial, and how long
+/// finished sessions are kept
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionRules {
+    /// Capacity the session must reach. Unset means the firmware charge stop
+    /// threshold if there is one, otherwise 90%.
+    pub target_percent: Option<f64>,
+    /// Minimum capacity gained from start to end, in percentage points
+    pub min_delta_percent: f64,
+    /// Minimum time spent charging, in seconds
+    pub min_duration_secs: u64,
+    /// Keep at most this many sessions (unset = unlimited)
+    pub max_sessions: Option<usize>,
+    /// Drop sessions that started more than this many days ago (unset = never)
+    pub retention_days: Option<u32>,
+}
+
+/// How often batteries are sampled and the history is saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sampling {
+    /// Seconds between samples
+    pub interval_secs: u64,
+    /// Seconds between saves of the history
+    pub save_interval_secs: u64,
+    /// Sample every `fast_interval_secs` while charging or under heavy drain,
+    /// every `slow_interval_secs` while idle on AC, `interval_secs` otherwise
+    pub adaptive: bool,
+    pub fast_interval_secs: u64,
+    pub slow_interval_secs: u64,
+    /// Drain, in W, from which discharging counts as heavy
+    pub heavy_drain_watts: f64,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Sampling {
+            interval_secs: 5,
+            save_interval_secs: 300,
+            adaptive: false,
+            fast_interval_secs: 2,
+            slow_interval_secs: 30,
+            heavy_drain_watts: 15.0,
+        }
+    }
+}
+
+impl Sampling {
+    /// Time to wait before the sample after `last` (the latest pack sample)
+    pub fn interval_after(&self, last: Option<&BatterySample>) -> Duration {
+        let secs = match last {
+            Some(sample) if self.adaptive => match sample.status {
+                BatteryStatus::Charging => self.fast_interval_secs,
+                BatteryStatus::Discharging if -sample.power_watts >= self.heavy_drain_watts => {
+                    self.fast_interval_secs
+                }
+                // Full or held at a threshold: plugged in and idle
+                BatteryStatus::Full | BatteryStatus::NotCharging => self.slow_interval_secs,
+                BatteryStatus::Discharging | BatteryStatus::Unknown => self.interval_secs,
+            },
+            _ => self.interval_secs,
+        };
+        Duration::from_secs(secs.max(1))
+    }
+
+    /// Gap between pack samples from which the machine counts as having
+    /// slept: four of the longest intervals, and at least a minute
+    pub fn sleep_gap(&self) -> Duration {
+        let longest = if self.adaptive {
+            self.slow_interval_secs.max(self.interval_secs)
+        } else {
+            self.interval_secs
+        };
+        Duration::from_secs((longest * 4).max(60))
+    }
+
+    pub fn save_interval(&self) -> Duration {
+        Duration::from_secs(self.save_interval_secs)
+    }
+
+    /// Describe the sampling rate, e.g. "every 5s" or "every 2-30s (adaptive)"
+    pub fn describe(&self) -> String {
+        if self.adaptive {
+            let fastest = self.fast_interval_secs.min(self.interval_secs).max(1);
+            let slowest = self.slow_interval_secs.max(self.interval_secs);
+            format!("every {fastest}-{slowest}s (adaptive)")
+        } else {
+            format!("every {}s", self.interval_secs.max(1))
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file at `path` (or the default location). A missing file
+    /// gives the defaults; an invalid one is reported on stderr and ignored.
+    pub fn load(path: Option<&Path>) -> Self {
+        let path = path
+            .map(Path::to_path_buf)
+            .unwrap_or_else(Self::config_path);
+        let Ok(data) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match toml::from_str(&data) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("bathis: ignoring invalid config {}: {e}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn config_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("bathis")
+            .join("config.toml")
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Failure to read the batteries or to save or export what was read
+#[derive(Debug)]
+pub enum Error {
+    /// The power supply directory could not be listed
+    PowerSupplies { root: PathBuf, source: io::Error },
+    /// The power supply directory has no system battery
+    NoBattery { root: PathBuf },
+    /// A sysfs attribute is missing or unreadable
+    Attribute { path: PathBuf, source: io::Error },
+    /// A sysfs attribute does not hold a number
+    InvalidAttribute { path: PathBuf, value: String },
+    /// Writing a sysfs attribute failed
+    AttributeWrite { path: PathBuf, source: io::Error },
+    /// Charge thresholds outside 0 <= start < end <= 100
+    InvalidThresholds { start: u8, end: u8 },
+    /// Writing to a database failed
+    Database {
+        path: PathBuf,
+        source: rusqlite::Error,
+    },
+    /// The daily backup of a database failed
+    Backup { path: PathBuf, source: io::Error },
+    /// Writing the metrics textfile failed
+    Textfile { path: PathBuf, source: io::Error },
+    /// A JSON file did not parse
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PowerSupplies { root, source } => {
+                write!(f, "cannot list {}: {source}", root.display())
+            }
+            Error::NoBattery { root } => write!(f, "no battery found in {}/", root.display()),
+            Error::Attribute { path, source } if source.kind() == io::ErrorKind::NotFound => {
+                write!(f, "{} is missing", path.display())
+            }
+            Error::Attribute { path, source } => {
+                write!(f, "cannot read {}: {source}", path.display())
+            }
+            Error::InvalidAttribute { path, value } => {
+                write!(f, "{} holds {value:?}, not a number", path.display())
+            }
+            Error::AttributeWrite { path, source }
+                if source.kind() == io::ErrorKind::PermissionDenied =>
+            {
+                write!(
+                    f,
+                    "cannot write {}: {source} (this needs root, try again with sudo)",
+                    path.display()
+                )
+            }
+            Error::AttributeWrite { path, source } => {
+                write!(f, "cannot write {}: {source}", path.display())
+            }
+            Error::InvalidThresholds { start, end } => write!(
+                f,
+                "thresholds must satisfy 0 <= start < end <= 100 (got {start} {end})"
+            ),
+            Error::Database { path, source } => {
+                write!(f, "cannot save to {}: {source}", path.display())
+            }
+            Error::Backup { path, source } => {
+                write!(f, "cannot back up {}: {source}", path.display())
+            }
+            Error::Textfile { path, source } => {
+                write!(f, "cannot write {}: {source}", path.display())
+            }
+            Error::Json { path, source } => {
+                write!(f, "cannot parse {}: {source}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::PowerSupplies { source, .. }
+            | Error::Attribute { source, .. }
+            | Error::AttributeWrite { source, .. }
+            | Error::Backup { source, .. }
+            | Error::Textfile { source, .. } => Some(source),
+            Error::Database { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::NoBattery { .. }
+            | Error::InvalidAttribute { .. }
+            | Error::InvalidThresholds { .. } => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        io::Error::other(e)
+    }
+}
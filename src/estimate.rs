@@ -0,0 +1,242 @@
+use crate::battery::{BatterySample, BatteryStatus, ChargeThresholds};
+use crate::history::{ChargeSession, SessionStats};
+
+/// Time constant of the power average: a sample's weight halves about
+/// every 3.5 minutes, so short bursts of load do not swing the estimate
+const SMOOTHING_SECS: f64 = 300.0;
+
+/// Where the constant-voltage taper starts when no charge curve is known
+const DEFAULT_TAPER_START: f64 = 80.0;
+
+/// Charging power at 100%, relative to the constant-current phase, when no
+/// charge curve is known
+const DEFAULT_TAPER_END: f64 = 0.2;
+
+/// Lowest relative charging power used, so a noisy curve cannot push the
+/// estimate towards infinity
+const MIN_RELATIVE_POWER: f64 = 0.05;
+
+/// Power of the latest run of samples with the same status, exponentially
+/// weighted by time. Samples before the status last changed do not count.
+pub fn smoothed_power(samples: &[BatterySample]) -> Option<f64> {
+    let last = samples.last()?;
+    let run = samples
+        .iter()
+        .rev()
+        .take_while(|s| s.status == last.status)
+        .count();
+    let run = &samples[samples.len() - run..];
+
+    let mut average = run[0].power_watts;
+    for pair in run.windows(2) {
+        let dt = (pair[1].timestamp - pair[0].timestamp).num_milliseconds() as f64 / 1000.0;
+        let alpha = 1.0 - (-dt.max(0.0) / SMOOTHING_SECS).exp();
+        average += (pair[1].power_watts - average) * alpha;
+    }
+    Some(average)
+}
+
+/// Charging power at each capacity percent relative to the peak power of
+/// the session, averaged over past charge sessions. It captures how much
+/// charging slows down in the constant-voltage phase.
+#[derive(Debug, Clone)]
+pub struct ChargeCurve {
+    relative_power: Vec<Option<f64>>,
+    /// Number of sessions the curve was learned from
+    pub sessions: usize,
+}
+
+impl ChargeCurve {
+    pub fn learn(sessions: &[ChargeSession]) -> Self {
+        let mut sums = vec![(0.0, 0u32); 100];
+        for session in sessions {
+            let peak = session.peak_watts();
+            if peak <= 0.0 {
+                continue;
+            }
+            for sample in session.samples.iter().filter(|s| s.power_watts > 0.0) {
+                let (sum, n) = &mut sums[percent_index(sample.capacity)];
+                *sum += sample.power_watts / peak;
+                *n += 1;
+            }
+        }
+        ChargeCurve {
+            relative_power: sums
+                .into_iter()
+                .map(|(sum, n)| (n > 0).then(|| sum / f64::from(n)))
+                .collect(),
+            sessions: sessions.len(),
+        }
+    }
+
+    /// Charging power at `capacity` relative to the session's peak, from
+    /// the learned curve or else a linear taper above 80%
+    fn relative_power(&self, capacity: f64) -> f64 {
+        let learned = self
+            .relative_power
+            .get(percent_index(capacity))
+            .copied()
+            .flatten();
+        let default = if capacity < DEFAULT_TAPER_START {
+            1.0
+        } else {
+            let progress = (capacity - DEFAULT_TAPER_START) / (100.0 - DEFAULT_TAPER_START);
+            1.0 - (1.0 - DEFAULT_TAPER_END) * progress
+        };
+        learned.unwrap_or(default).max(MIN_RELATIVE_POWER)
+    }
+}
+
+impl Default for ChargeCurve {
+    fn default() -> Self {
+        ChargeCurve::learn(&[])
+    }
+}
+
+/// How long until the battery is empty or charged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Remaining {
+    /// Seconds until empty
+    Empty(f64),
+    /// Seconds until `target` %
+    Full { secs: f64, target: f64 },
+}
+
+/// Level a charge is heading for: the stop threshold, or else 100%
+pub fn charge_target(thresholds: Option<&ChargeThresholds>) -> f64 {
+    thresholds.and_then(|t| t.end).map_or(100.0, f64::from)
+}
+
+/// Time left at the smoothed power of `samples`, whose last one is the
+/// current reading: until empty while discharging, until `target` % while
+/// charging
+pub fn remaining(samples: &[BatterySample], target: f64, curve: &ChargeCurve) -> Option<Remaining> {
+    let sample = samples.last()?;
+    let power = smoothed_power(samples)?;
+    match sample.status {
+        BatteryStatus::Discharging => time_to_empty(sample, power).map(Remaining::Empty),
+        BatteryStatus::Charging => {
+            time_to_full(sample, power, target, curve).map(|secs| Remaining::Full { secs, target })
+        }
+        _ => None,
+    }
+}
+
+/// Seconds until empty at a drain of `power` W (negative while discharging)
+pub fn time_to_empty(sample: &BatterySample, power: f64) -> Option<f64> {
+    if sample.status != BatteryStatus::Discharging || power >= 0.0 {
+        return None;
+    }
+    let energy = if sample.energy_now_wh > 0.0 {
+        sample.energy_now_wh
+    } else {
+        sample.capacity / 100.0 * sample.energy_full_wh
+    };
+    (energy > 0.0).then(|| energy / -power * 3600.0)
+}
+
+/// Seconds until `target` % when charging at `power` W now. Each percent
+/// still to go is charged at `power` scaled by the curve, so the taper
+/// towards full is accounted for. `None` once `target` is reached, e.g.
+/// while charging is held at the stop threshold.
+pub fn time_to_full(
+    sample: &BatterySample,
+    power: f64,
+    target: f64,
+    curve: &ChargeCurve,
+) -> Option<f64> {
+    if sample.status != BatteryStatus::Charging
+        || power <= 0.0
+        || sample.energy_full_wh <= 0.0
+        || sample.capacity >= target
+    {
+        return None;
+    }
+    let wh_per_percent = sample.energy_full_wh / 100.0;
+    let now = curve.relative_power(sample.capacity);
+    let mut capacity = sample.capacity;
+    let mut secs = 0.0;
+    while capacity < target {
+        let step = (capacity.floor() + 1.0).min(target) - capacity;
+        let watts = power * curve.relative_power(capacity) / now;
+        secs += step * wh_per_percent / watts * 3600.0;
+        capacity += step;
+    }
+    Some(secs)
+}
+
+fn percent_index(capacity: f64) -> usize {
+    (capacity.max(0.0) as usize).min(99)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sample;
+
+    #[test]
+    fn smoothing_follows_the_current_status_and_damps_spikes() {
+        let mut samples = vec![sample(0, 50.0, 40.0, BatteryStatus::Charging)];
+        samples.extend((1..=60).map(|i| sample(i * 5, 50.0, -10.0, BatteryStatus::Discharging)));
+        samples.push(sample(305, 50.0, -40.0, BatteryStatus::Discharging));
+
+        let power = smoothed_power(&samples).unwrap();
+        assert!(power < -10.0 && power > -11.0, "{power}");
+    }
+
+    #[test]
+    fn time_to_empty_uses_the_energy_left() {
+        let now = sample(0, 50.0, -10.0, BatteryStatus::Discharging);
+        assert_eq!(time_to_empty(&now, -12.5), Some(2.0 * 3600.0));
+        assert_eq!(time_to_empty(&now, 3.0), None);
+    }
+
+    #[test]
+    fn time_to_full_slows_down_as_learned() {
+        // Past session: 50 W up to 80%, then 10 W
+        let samples = (0..100)
+            .map(|c| {
+                let power = if c < 80 { 50.0 } else { 10.0 };
+                sample(
+                    i64::from(c) * 60,
+                    f64::from(c),
+                    power,
+                    BatteryStatus::Charging,
+                )
+            })
+            .collect();
+        let session = ChargeSession {
+            start_time: sample(0, 0.0, 0.0, BatteryStatus::Charging).timestamp,
+            end_time: None,
+            start_capacity: 0.0,
+            end_capacity: 99.0,
+            samples,
+            completed: true,
+            charger: None,
+        };
+        let curve = ChargeCurve::learn(&[session]);
+
+        // 20 points at 50 W, then 20 points at 10 W; 1% is 0.5 Wh
+        let now = sample(0, 60.0, 50.0, BatteryStatus::Charging);
+        let secs = time_to_full(&now, 50.0, 100.0, &curve).unwrap();
+        assert!((secs - (10.0 / 50.0 + 10.0 / 10.0) * 3600.0).abs() < 1e-6);
+
+        let flat = time_to_full(&now, 50.0, 80.0, &ChargeCurve::default()).unwrap();
+        assert!((flat - 10.0 / 50.0 * 3600.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn no_time_to_full_once_the_target_is_reached() {
+        let at_limit = sample(0, 80.0, 2.0, BatteryStatus::Charging);
+        assert_eq!(
+            time_to_full(&at_limit, 2.0, 80.0, &ChargeCurve::default()),
+            None
+        );
+        let above = sample(0, 81.0, 2.0, BatteryStatus::Charging);
+        assert_eq!(
+            time_to_full(&above, 2.0, 80.0, &ChargeCurve::default()),
+            None
+        );
+        assert!(time_to_full(&above, 2.0, 100.0, &ChargeCurve::default()).is_some());
+    }
+}
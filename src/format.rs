@@ -0,0 +1,23 @@
+use crate::estimate::Remaining;
+
+/// Format seconds to a human-readable duration
+pub fn format_duration(secs: f64) -> String {
+    let total_secs = secs as u64;
+    let hours = total_secs / 3600;
+    let minutes = (total_secs % 3600) / 60;
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+/// Format a time left estimate, e.g. "~2h 05m until empty"
+pub fn format_remaining(remaining: Remaining) -> String {
+    match remaining {
+        Remaining::Empty(secs) => format!("~{} until empty", format_duration(secs)),
+        Remaining::Full { secs, target } => {
+            format!("~{} until {target:.0}%", format_duration(secs))
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::history::set_aside;
+use crate::store::Store;
+
+/// Static and slowly changing battery facts read from sysfs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatteryHealth {
+    /// Sysfs name, e.g. "BAT0"
+    pub battery: String,
+    pub cycle_count: Option<u32>,
+    pub energy_full_wh: f64,
+    pub energy_full_design_wh: f64,
+    pub technology: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+impl BatteryHealth {
+    /// Remaining capacity relative to design: energy_full / energy_full_design, in percent
+    pub fn health_percent(&self) -> Option<f64> {
+        (self.energy_full_design_wh > 0.0)
+            .then(|| self.energy_full_wh / self.energy_full_design_wh * 100.0)
+    }
+
+    /// Capacity lost to wear, in percent (100 − health)
+    pub fn wear_percent(&self) -> Option<f64> {
+        self.health_percent().map(|h| (100.0 - h).max(0.0))
+    }
+}
+
+/// One battery's health on one day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthSnapshot {
+    pub date: NaiveDate,
+    pub health: BatteryHealth,
+}
+
+/// Long-term daily health series, stored apart from the sample history
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HealthLog {
+    pub snapshots: Vec<HealthSnapshot>,
+    /// Set when loading did not go as expected, for the user to see
+    #[serde(skip)]
+    pub load_warning: Option<String>,
+    /// Database the log is saved to (None = not persisted)
+    #[serde(skip)]
+    store: Option<Store>,
+    /// Another process (the recorder) writes the log
+    #[serde(skip)]
+    read_only: bool,
+}
+
+impl HealthLog {
+    pub fn new() -> Self {
+        HealthLog::default()
+    }
+
+    /// Load the health log from the database, or create new if not found.
+    /// An existing health.json is imported the first time. A damaged
+    /// database is replaced by its newest good backup; `load_warning` then
+    /// says so.
+    pub fn load() -> Self {
+        let (store, warning) = Store::open_or_recover(&Store::db_path());
+        let mut log = match store {
+            Some(store) => Self::open(store, &Self::json_path()),
+            None => Self::new(),
+        };
+        if let Some(warning) = warning {
+            log.load_warning = Some(warning);
+        }
+        log
+    }
+
+    /// Open the log another process (the recorder) is writing, without
+    /// writing to it. `reload` picks up what the writer saves later on.
+    pub fn load_following() -> Self {
+        let mut log = Self::new();
+        log.read_only = true;
+        let path = Store::db_path();
+        // No database yet is no news; the history says so already
+        if !path.exists() {
+            return log;
+        }
+        match Store::open_read_only(&path) {
+            Ok(store) => {
+                match store.load_health() {
+                    Ok(snapshots) => log.snapshots = snapshots,
+                    Err(e) => log.load_warning = Some(format!("cannot read the health log ({e})")),
+                }
+                log.store = Some(store);
+            }
+            Err(e) => log.load_warning = Some(format!("cannot open the health log ({e})")),
+        }
+        log
+    }
+
+    /// Become the writer once the recorder has stopped
+    pub fn take_over(&mut self) {
+        *self = Self::load();
+    }
+
+    fn open(mut store: Store, json_path: &Path) -> Self {
+        let mut log = HealthLog::new();
+        match store.load_health() {
+            Ok(snapshots) => log.snapshots = snapshots,
+            Err(e) => log.load_warning = Some(format!("cannot read the health log ({e})")),
+        }
+        if log.snapshots.is_empty()
+            && log.load_warning.is_none()
+            && let Ok(data) = fs::read_to_string(json_path)
+        {
+            match serde_json::from_str::<HealthLog>(&data) {
+                Ok(imported) => {
+                    let all: Vec<&HealthSnapshot> = imported.snapshots.iter().collect();
+                    let written = store.write_health(&all).map_err(|source| Error::Database {
+                        path: store.path().to_path_buf(),
+                        source,
+                    });
+                    let target = json_path.with_extension("json.imported");
+                    log.load_warning = match written.map(|()| fs::rename(json_path, &target)) {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(format!(
+                            "imported {} but cannot rename it to {} ({e})",
+                            json_path.display(),
+                            target.display()
+                        )),
+                        Err(e) => Some(e.to_string()),
+                    };
+                    log.snapshots = imported.snapshots;
+                }
+                Err(source) => log.load_warning = Some(set_aside(json_path, source)),
+            }
+        }
+        log.store = Some(store);
+        log
+    }
+
+    /// Re-read the snapshots, to pick up what another writer saved
+    pub fn reload(&mut self) {
+        if let Some(snapshots) = self.store.as_ref().and_then(|s| s.load_health().ok()) {
+            self.snapshots = snapshots;
+        }
+    }
+
+    /// Save today's snapshots to the database (earlier days never change)
+    pub fn save(&mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        let today = Local::now().date_naive();
+        let changed: Vec<&HealthSnapshot> =
+            self.snapshots.iter().filter(|s| s.date == today).collect();
+        let Some(store) = &mut self.store else {
+            return Ok(());
+        };
+        store
+            .write_health(&changed)
+            .map_err(|source| Error::Database {
+                path: store.path().to_path_buf(),
+                source,
+            })
+    }
+
+    fn json_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("bathis")
+            .join("health.json")
+    }
+
+    /// Record today's reading for each battery. Later readings on the same day
+    /// replace the earlier one. Returns true if anything changed.
+    pub fn record(&mut self, readings: &[BatteryHealth]) -> bool {
+        let today = Local::now().date_naive();
+        let mut changed = false;
+        for reading in readings {
+            let existing = self
+                .snapshots
+                .iter_mut()
+                .find(|s| s.date == today && s.health.battery == reading.battery);
+            match existing {
+                Some(snapshot) if snapshot.health == *reading => {}
+                Some(snapshot) => {
+                    snapshot.health = reading.clone();
+                    changed = true;
+                }
+                None => {
+                    self.snapshots.push(HealthSnapshot {
+                        date: today,
+                        health: reading.clone(),
+                    });
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Snapshots for one battery, oldest first
+    pub fn series(&self, battery: &str) -> Vec<&HealthSnapshot> {
+        self.snapshots
+            .iter()
+            .filter(|s| s.health.battery == battery)
+            .collect()
+    }
+
+    /// Battery names that appear in the log
+    pub fn batteries(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .snapshots
+            .iter()
+            .map(|s| s.health.battery.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn json_import_problems_become_load_warnings() {
+        let dir = temp_dir("health");
+        let json = dir.join("health.json");
+
+        fs::write(&json, "{ not json").unwrap();
+        let log = HealthLog::open(Store::open(&dir.join("broken.db")).unwrap(), &json);
+        assert!(log.snapshots.is_empty());
+        assert!(log.load_warning.unwrap().contains("health.json.corrupt"));
+        assert!(!json.exists());
+
+        let snapshot = HealthSnapshot {
+            date: Local::now().date_naive(),
+            health: BatteryHealth {
+                battery: "BAT0".to_string(),
+                cycle_count: Some(120),
+                energy_full_wh: 45.0,
+                energy_full_design_wh: 50.0,
+                technology: None,
+                serial_number: None,
+            },
+        };
+        let old = HealthLog {
+            snapshots: vec![snapshot],
+            ..HealthLog::new()
+        };
+        fs::write(&json, serde_json::to_string(&old).unwrap()).unwrap();
+        let db = dir.join("history.db");
+        let log = HealthLog::open(Store::open(&db).unwrap(), &json);
+        assert_eq!(log.load_warning, None);
+        assert_eq!(log.snapshots.len(), 1);
+        assert!(json.with_extension("json.imported").exists());
+        let reopened = HealthLog::open(Store::open(&db).unwrap(), &json);
+        assert_eq!(reopened.snapshots[0].health.cycle_count, Some(120));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
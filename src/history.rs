@@ -1,12 +1,28 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
-use crate::battery::{BatterySample, BatteryStatus};
+use crate::battery::{BatterySample, BatteryStatus, describe_adapters};
+use crate::config::SessionRules;
+use crate::error::Error;
+use crate::rollup::{self, Resolution, Rollup};
+use crate::store::{SessionRow, SessionWrite, Store, WriteBatch};
 
-/// A single charge session: from start of charging to reaching 90%+
+/// How old the last sample of a saved in-progress session may be for the
+/// session to be resumed after a restart
+const RESUME_WINDOW_SECS: i64 = 10 * 60;
+
+/// Most points a history chart line is drawn with before switching to a
+/// coarser resolution
+const MAX_CHART_POINTS: usize = 3000;
+
+/// A rollup tier of one series (battery `None` = combined pack)
+type RollupKey = (Resolution, Option<String>);
+
+/// A single charge session: from start of charging until charging stops
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChargeSession {
     pub start_time: DateTime<Local>,
@@ -14,65 +30,741 @@ pub struct ChargeSession {
     pub start_capacity: f64,
     pub end_capacity: f64,
     pub samples: Vec<BatterySample>,
-    pub completed: bool, // reached 90%+
+    /// Met the session rules (reached the target, e.g. 90%+); `false` marks a partial session
+    pub completed: bool,
+    /// Charger used, e.g. "USB-C PD 65 W" (first one seen during the session)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charger: Option<String>,
+}
+
+/// A single discharge session: from unplugging until the charger is plugged in again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DischargeSession {
+    pub start_time: DateTime<Local>,
+    pub end_time: Option<DateTime<Local>>,
+    pub start_capacity: f64,
+    pub end_capacity: f64,
+    pub samples: Vec<BatterySample>,
+}
+
+/// A stretch the machine spent suspended, seen as a gap in the pack samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct SleepPeriod {
+    /// Last sample before the gap
+    pub start: DateTime<Local>,
+    /// First sample after it
+    pub end: DateTime<Local>,
+    pub start_capacity: f64,
+    pub end_capacity: f64,
+    /// Discharging on both sides of the gap, so the capacity lost is sleep drain
+    pub on_battery: bool,
+}
+
+impl SleepPeriod {
+    pub fn hours(&self) -> f64 {
+        (self.end - self.start).num_seconds() as f64 / 3600.0
+    }
+
+    /// Capacity lost while asleep, in percentage points
+    pub fn capacity_lost(&self) -> f64 {
+        (self.start_capacity - self.end_capacity).max(0.0)
+    }
+}
+
+/// Sleep drain over all sleeps spent on battery
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SleepSummary {
+    pub count: usize,
+    pub hours: f64,
+    /// Capacity lost per hour of sleep, in percentage points
+    pub drain_per_hour: f64,
+}
+
+/// Which kind of session a list or detail view shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionKind {
+    Charge,
+    Discharge,
+}
+
+impl SessionKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            SessionKind::Charge => "Charge",
+            SessionKind::Discharge => "Discharge",
+        }
+    }
+}
+
+/// Figures shared by charge and discharge sessions
+pub trait SessionStats {
+    fn start_time(&self) -> DateTime<Local>;
+    fn end_time(&self) -> Option<DateTime<Local>>;
+    fn samples(&self) -> &[BatterySample];
+
+    /// Energy added (charge) or used (discharge), in Wh
+    fn energy_wh(&self) -> f64;
+
+    /// Time from start to end of the session, in seconds
+    fn duration_secs(&self) -> f64 {
+        self.end_time()
+            .map(|e| (e - self.start_time()).num_seconds() as f64)
+            .unwrap_or(0.0)
+    }
+
+    /// Mean charging power or drain over the session, in W. Each sample
+    /// counts for the time since the one before, so the spacing may vary.
+    fn average_watts(&self) -> f64 {
+        let samples = self.samples();
+        let (watt_secs, secs) = samples.windows(2).fold((0.0, 0.0), |(ws, t), pair| {
+            let dt = (pair[1].timestamp - pair[0].timestamp).num_milliseconds() as f64 / 1000.0;
+            (ws + pair[1].power_watts.abs() * dt, t + dt)
+        });
+        if secs > 0.0 {
+            return watt_secs / secs;
+        }
+        if samples.is_empty() {
+            return 0.0;
+        }
+        samples.iter().map(|s| s.power_watts.abs()).sum::<f64>() / samples.len() as f64
+    }
+
+    /// Highest charging power or drain seen, in W
+    fn peak_watts(&self) -> f64 {
+        self.samples()
+            .iter()
+            .map(|s| s.power_watts.abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+impl ChargeSession {
+    fn from_row(row: SessionRow, samples: Vec<BatterySample>) -> Self {
+        ChargeSession {
+            start_time: row.start_time,
+            end_time: row.end_time,
+            start_capacity: row.start_capacity,
+            end_capacity: row.end_capacity,
+            samples,
+            completed: row.completed,
+            charger: row.charger,
+        }
+    }
+
+    fn to_write(&self, active: bool) -> SessionWrite<'_> {
+        SessionWrite {
+            kind: SessionKind::Charge,
+            row: SessionRow {
+                start_time: self.start_time,
+                end_time: self.end_time,
+                start_capacity: self.start_capacity,
+                end_capacity: self.end_capacity,
+                completed: self.completed,
+                charger: self.charger.clone(),
+                active,
+            },
+            samples: &self.samples,
+        }
+    }
+}
+
+impl DischargeSession {
+    fn to_write(&self, active: bool) -> SessionWrite<'_> {
+        SessionWrite {
+            kind: SessionKind::Discharge,
+            row: SessionRow {
+                start_time: self.start_time,
+                end_time: self.end_time,
+                start_capacity: self.start_capacity,
+                end_capacity: self.end_capacity,
+                completed: false,
+                charger: None,
+                active,
+            },
+            samples: &self.samples,
+        }
+    }
+}
+
+/// Energy difference between the first and last sample, falling back to
+/// capacity × full energy for batteries that report no energy
+fn energy_delta_wh(samples: &[BatterySample]) -> f64 {
+    match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) if first.energy_now_wh > 0.0 => {
+            last.energy_now_wh - first.energy_now_wh
+        }
+        (Some(first), Some(last)) => (last.capacity - first.capacity) / 100.0 * last.energy_full_wh,
+        _ => 0.0,
+    }
+}
+
+impl SessionStats for ChargeSession {
+    fn start_time(&self) -> DateTime<Local> {
+        self.start_time
+    }
+
+    fn end_time(&self) -> Option<DateTime<Local>> {
+        self.end_time
+    }
+
+    fn samples(&self) -> &[BatterySample] {
+        &self.samples
+    }
+
+    fn energy_wh(&self) -> f64 {
+        energy_delta_wh(&self.samples).max(0.0)
+    }
+}
+
+impl SessionStats for DischargeSession {
+    fn start_time(&self) -> DateTime<Local> {
+        self.start_time
+    }
+
+    fn end_time(&self) -> Option<DateTime<Local>> {
+        self.end_time
+    }
+
+    fn samples(&self) -> &[BatterySample] {
+        &self.samples
+    }
+
+    fn energy_wh(&self) -> f64 {
+        (-energy_delta_wh(&self.samples)).max(0.0)
+    }
 }
 
 /// Persistent history storage
 #[derive(Debug, Serialize, Deserialize)]
 pub struct History {
-    /// All samples in current monitoring session
+    /// All samples in current monitoring session (combined pack)
     pub samples: Vec<BatterySample>,
-    /// Last 2 completed charge sessions (reached 90%+)
+    /// Per-battery samples, keyed by sysfs name (only on multi-battery systems)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub battery_samples: BTreeMap<String, Vec<BatterySample>>,
+    /// Finished charge sessions, completed and partial, oldest first
     pub charge_sessions: Vec<ChargeSession>,
-    /// Currently active charge session (if charging)
-    #[serde(skip)]
+    /// Finished discharge sessions (unplug to plug-in), oldest first
+    #[serde(default)]
+    pub discharge_sessions: Vec<DischargeSession>,
+    /// Currently active charge session (if charging), saved so a restart can resume it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub active_session: Option<ChargeSession>,
+    /// Currently active discharge session (if on battery)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_discharge: Option<DischargeSession>,
+    /// Firmware charge stop threshold, which replaces the default completion target
+    #[serde(skip)]
+    pub charge_end_threshold: Option<u8>,
+    /// Rules deciding whether a finished session is completed or partial
+    #[serde(skip)]
+    pub rules: SessionRules,
+    /// Database the history is saved to (None = not persisted)
+    #[serde(skip)]
+    store: Option<Store>,
+    /// Changes not written to the store yet
+    #[serde(skip)]
+    pending: PendingWrites,
+    /// Problem found while loading (damaged database, unreadable import), for the user
+    #[serde(skip)]
+    pub load_warning: Option<String>,
+    /// Another process writes the database; this history only follows it
+    #[serde(skip)]
+    read_only: bool,
+    /// Store version last loaded while following
+    #[serde(skip)]
+    data_version: i64,
+    /// Per-minute and per-quarter-hour rollups of every series, oldest first
+    #[serde(skip)]
+    rollups: BTreeMap<RollupKey, Vec<Rollup>>,
+    /// Suspends seen in the pack samples, oldest first
+    #[serde(skip)]
+    pub sleeps: Vec<SleepPeriod>,
+    /// Gap between pack samples from which the machine counts as asleep
+    #[serde(skip)]
+    pub sleep_gap: chrono::Duration,
+    /// A pack sample was added since loading. Only gaps after it are
+    /// sleeps; the one before may be time bathis was not running.
+    #[serde(skip)]
+    sampling: bool,
+}
+
+/// What `History::save` still has to write
+#[derive(Debug, Default)]
+struct PendingWrites {
+    /// New pack samples at the end of `samples`
+    samples: usize,
+    /// New samples at the end of each battery's series
+    battery_samples: BTreeMap<String, usize>,
+    /// Sessions finished since the last save
+    closed_sessions: Vec<(SessionKind, DateTime<Local>)>,
+    /// New or updated buckets at the end of each rollup series
+    rollups: BTreeMap<RollupKey, usize>,
+    /// New sleeps at the end of `sleeps`
+    sleeps: usize,
 }
 
 impl History {
     pub fn new() -> Self {
         History {
             samples: Vec::new(),
+            battery_samples: BTreeMap::new(),
             charge_sessions: Vec::new(),
+            discharge_sessions: Vec::new(),
             active_session: None,
+            active_discharge: None,
+            charge_end_threshold: None,
+            rules: SessionRules::default(),
+            store: None,
+            pending: PendingWrites::default(),
+            load_warning: None,
+            read_only: false,
+            data_version: 0,
+            rollups: BTreeMap::new(),
+            sleeps: Vec::new(),
+            sleep_gap: chrono::Duration::seconds(60),
+            sampling: false,
         }
     }
 
-    /// Load history from disk, or create new if not found
+    /// Load history from the database, or create new if there is none. The
+    /// first time, an existing history.json is imported and renamed to
+    /// history.json.imported. A damaged database is replaced by its newest
+    /// good backup; `load_warning` then says so.
     pub fn load() -> Self {
-        let path = Self::data_path();
-        if path.exists()
-            && let Ok(data) = fs::read_to_string(&path)
-            && let Ok(history) = serde_json::from_str::<History>(&data)
-        {
+        let (store, warning) = Store::open_or_recover(&Store::db_path());
+        let mut history = match store {
+            Some(store) => Self::open(store, &Self::json_path()),
+            None => Self::new(),
+        };
+        if let Some(warning) = warning {
+            history.load_warning = Some(warning);
+        }
+        history
+    }
+
+    /// Open the history another process (the recorder) is writing, without
+    /// writing to it. `refresh` picks up what the writer saves later on.
+    pub fn load_following() -> Self {
+        let path = Store::db_path();
+        if !path.exists() {
+            let mut history = Self::new();
+            history.load_warning = Some("no history yet".to_string());
+            history.read_only = true;
+            return history;
+        }
+        let store = match Store::open_read_only(&path) {
+            Ok(store) => store,
+            Err(e) => {
+                let mut history = Self::new();
+                history.load_warning = Some(format!("cannot open the history database ({e})"));
+                return history;
+            }
+        };
+        let mut history = Self::read_store(&store).unwrap_or_else(|e| {
+            let mut history = Self::new();
+            history.load_warning = Some(format!("cannot read the history database ({e})"));
+            history
+        });
+        history.data_version = store.data_version().unwrap_or(0);
+        history.store = Some(store);
+        history.read_only = true;
+        history
+    }
+
+    /// Read only what time left estimates need: the pack samples taken
+    /// since `since` and the finished charge sessions. The database is
+    /// opened read-only and nothing is kept open, for one-shot commands.
+    pub fn load_recent(since: DateTime<Local>) -> Self {
+        let read = || -> rusqlite::Result<Self> {
+            let store = Store::open_read_only(&Store::db_path())?;
+            let mut history = Self::new();
+            history.samples = store.load_samples_since(None, since)?;
+            for (row, samples) in store.load_finished_sessions(SessionKind::Charge)? {
+                history
+                    .charge_sessions
+                    .push(ChargeSession::from_row(row, samples));
+            }
+            Ok(history)
+        };
+        read().unwrap_or_else(|e| {
+            let mut history = Self::new();
+            history.load_warning = Some(format!("cannot read the history database ({e})"));
+            history
+        })
+    }
+
+    /// True while another process writes the history
+    pub fn is_following(&self) -> bool {
+        self.read_only
+    }
+
+    /// While following, reload when the writer has saved something new
+    pub fn refresh(&mut self) {
+        if !self.read_only {
+            return;
+        }
+        let Some(version) = self.store.as_ref().and_then(|s| s.data_version().ok()) else {
+            return;
+        };
+        if version != self.data_version {
+            self.data_version = version;
+            self.reload();
+        }
+    }
+
+    /// Become the writer once the recorder has stopped. Samples taken since
+    /// its last save are kept and written with the next save.
+    pub fn take_over(&mut self) {
+        // Following used a read-only connection
+        let path = self
+            .store
+            .take()
+            .map_or_else(Store::db_path, |store| store.path().to_path_buf());
+        let (store, warning) = Store::open_or_recover(&path);
+        self.store = store;
+        if warning.is_some() {
+            self.load_warning = warning;
+        }
+        self.reload();
+        self.read_only = false;
+    }
+
+    /// Replace the history with the database contents, then add back the
+    /// samples in memory that are newer than anything stored
+    fn reload(&mut self) {
+        let Some(store) = self.store.take() else {
+            return;
+        };
+        if let Ok(stored) = Self::read_store(&store) {
+            let newer_than = |stored: &[BatterySample], series: Vec<BatterySample>| {
+                let newest = stored.last().map(|s| s.timestamp);
+                series
+                    .into_iter()
+                    .filter(|s| newest.is_none_or(|t| s.timestamp > t))
+                    .collect::<Vec<_>>()
+            };
+            let mut unsaved = Vec::new();
+            for (name, series) in std::mem::take(&mut self.battery_samples) {
+                let stored_series = stored.samples_for(Some(&name));
+                unsaved.extend(newer_than(stored_series, series));
+            }
+            unsaved.extend(newer_than(
+                &stored.samples,
+                std::mem::take(&mut self.samples),
+            ));
+
+            self.samples = stored.samples;
+            self.battery_samples = stored.battery_samples;
+            self.charge_sessions = stored.charge_sessions;
+            self.discharge_sessions = stored.discharge_sessions;
+            self.active_session = stored.active_session;
+            self.active_discharge = stored.active_discharge;
+            self.rollups = stored.rollups;
+            self.sleeps = stored.sleeps;
+            self.pending = PendingWrites::default();
+            for sample in unsaved {
+                self.add_sample(sample);
+            }
+        }
+        self.store = Some(store);
+    }
+
+    /// Read history from `store`, importing the JSON history at `json_path`
+    /// if the store is still empty
+    fn open(store: Store, json_path: &Path) -> Self {
+        if store.has_history().unwrap_or(true) {
+            return match Self::read_store(&store) {
+                Ok(mut history) => {
+                    history.store = Some(store);
+                    history
+                }
+                // Saving an empty history would trim away what is stored, so
+                // leave the database alone for this run
+                Err(e) => {
+                    let mut history = Self::new();
+                    history.load_warning = Some(format!(
+                        "cannot read the history database ({e}); history is not saved"
+                    ));
+                    history
+                }
+            };
+        }
+
+        let mut history = Self::new();
+        history.store = Some(store);
+        let Ok(data) = fs::read_to_string(json_path) else {
             return history;
+        };
+        match serde_json::from_str::<History>(&data) {
+            Ok(imported) => {
+                history.import(imported);
+                let imported = json_path.with_extension("json.imported");
+                let renamed = history
+                    .write_pending()
+                    .map(|()| fs::rename(json_path, &imported));
+                history.load_warning = match renamed {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(format!(
+                        "imported {} but cannot rename it to {} ({e})",
+                        json_path.display(),
+                        imported.display()
+                    )),
+                    Err(e) => Some(e.to_string()),
+                };
+            }
+            Err(source) => history.load_warning = Some(set_aside(json_path, source)),
         }
-        Self::new()
+        history
     }
 
-    /// Save history to disk
-    pub fn save(&self) {
-        let path = Self::data_path();
-        if let Some(parent) = path.parent() {
-            let _ = fs::create_dir_all(parent);
+    fn read_store(store: &Store) -> rusqlite::Result<Self> {
+        let mut history = Self::new();
+        history.samples = store.load_samples(None, usize::MAX)?;
+        truncate_samples(&mut history.samples);
+        for name in store.battery_names()? {
+            let mut series = store.load_samples(Some(&name), usize::MAX)?;
+            truncate_samples(&mut series);
+            history.battery_samples.insert(name, series);
+        }
+        for (resolution, battery, bucket) in store.load_rollups()? {
+            history
+                .rollups
+                .entry((resolution, battery))
+                .or_default()
+                .push(bucket);
+        }
+        // Databases from before rollups existed only have raw samples
+        if history.rollups.is_empty() {
+            history.rebuild_rollups();
+        }
+        history.sleeps = store.load_sleeps()?;
+        for (row, samples) in store.load_sessions(SessionKind::Charge)? {
+            let active = row.active;
+            let session = ChargeSession::from_row(row, samples);
+            if active {
+                history.active_session = Some(session);
+            } else {
+                history.charge_sessions.push(session);
+            }
+        }
+        for (row, samples) in store.load_sessions(SessionKind::Discharge)? {
+            let active = row.active;
+            let session = DischargeSession {
+                start_time: row.start_time,
+                end_time: row.end_time,
+                start_capacity: row.start_capacity,
+                end_capacity: row.end_capacity,
+                samples,
+            };
+            if active {
+                history.active_discharge = Some(session);
+            } else {
+                history.discharge_sessions.push(session);
+            }
+        }
+        Ok(history)
+    }
+
+    /// Take over everything from a JSON history and mark it all as unsaved
+    fn import(&mut self, imported: History) {
+        self.pending.samples = imported.samples.len();
+        self.pending.battery_samples = imported
+            .battery_samples
+            .iter()
+            .map(|(name, series)| (name.clone(), series.len()))
+            .collect();
+        self.pending.closed_sessions = imported
+            .charge_sessions
+            .iter()
+            .map(|s| (SessionKind::Charge, s.start_time))
+            .chain(
+                imported
+                    .discharge_sessions
+                    .iter()
+                    .map(|s| (SessionKind::Discharge, s.start_time)),
+            )
+            .collect();
+        self.samples = imported.samples;
+        self.battery_samples = imported.battery_samples;
+        self.charge_sessions = imported.charge_sessions;
+        self.discharge_sessions = imported.discharge_sessions;
+        self.active_session = imported.active_session;
+        self.active_discharge = imported.active_discharge;
+        self.rebuild_rollups();
+    }
+
+    /// Roll up the raw samples held in memory and mark all rollups as unsaved
+    fn rebuild_rollups(&mut self) {
+        let series = std::iter::once((None, &self.samples)).chain(
+            self.battery_samples
+                .iter()
+                .map(|(name, samples)| (Some(name.clone()), samples)),
+        );
+        let mut rollups = BTreeMap::new();
+        for (battery, samples) in series {
+            if samples.is_empty() {
+                continue;
+            }
+            for resolution in Resolution::ROLLUPS {
+                rollups.insert(
+                    (resolution, battery.clone()),
+                    rollup::build(resolution, samples),
+                );
+            }
+        }
+        self.pending.rollups = rollups
+            .iter()
+            .map(|(key, series): (&RollupKey, &Vec<Rollup>)| (key.clone(), series.len()))
+            .collect();
+        self.rollups = rollups;
+    }
+
+    /// Write new samples and session changes to the database (not while
+    /// following another writer). After a failed write the changes stay
+    /// pending for the next save.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.write_pending()
+    }
+
+    fn write_pending(&mut self) -> Result<(), Error> {
+        let Some(mut store) = self.store.take() else {
+            return Ok(());
+        };
+        let result = store
+            .write(&self.pending_batch())
+            .map_err(|source| Error::Database {
+                path: store.path().to_path_buf(),
+                source,
+            })
+            .and_then(|()| {
+                self.pending = PendingWrites::default();
+                store.backup_if_due().map_err(|source| Error::Backup {
+                    path: store.path().to_path_buf(),
+                    source,
+                })
+            });
+        self.store = Some(store);
+        result.map(|_| ())
+    }
+
+    /// Collect the unsaved changes into one batch for the store
+    fn pending_batch(&self) -> WriteBatch<'_> {
+        let mut batch = WriteBatch::default();
+
+        let tail = |series: &[BatterySample], new: usize| -> usize {
+            series.len() - new.min(series.len())
+        };
+        batch
+            .samples
+            .extend(&self.samples[tail(&self.samples, self.pending.samples)..]);
+        for (name, series) in &self.battery_samples {
+            let new = self.pending.battery_samples.get(name).copied().unwrap_or(0);
+            batch.samples.extend(&series[tail(series, new)..]);
+        }
+
+        for &(kind, start) in &self.pending.closed_sessions {
+            let write = match kind {
+                SessionKind::Charge => self
+                    .charge_sessions
+                    .iter()
+                    .find(|s| s.start_time == start)
+                    .map(|s| s.to_write(false)),
+                SessionKind::Discharge => self
+                    .discharge_sessions
+                    .iter()
+                    .find(|s| s.start_time == start)
+                    .map(|s| s.to_write(false)),
+            };
+            batch.sessions.extend(write);
+        }
+        batch
+            .sessions
+            .extend(self.active_session.as_ref().map(|s| s.to_write(true)));
+        batch
+            .sessions
+            .extend(self.active_discharge.as_ref().map(|s| s.to_write(true)));
+
+        // Keep the database in step with what is held in memory
+        if let Some(first) = self.samples.first() {
+            batch.keep_samples_from.push((None, first.timestamp));
+        }
+        for (name, series) in &self.battery_samples {
+            if let Some(first) = series.first() {
+                batch
+                    .keep_samples_from
+                    .push((Some(name.as_str()), first.timestamp));
+            }
         }
-        if let Ok(data) = serde_json::to_string_pretty(self) {
-            let _ = fs::write(&path, data);
+        for ((resolution, battery), series) in &self.rollups {
+            let new = self
+                .pending
+                .rollups
+                .get(&(*resolution, battery.clone()))
+                .copied();
+            let new = new.unwrap_or(0).min(series.len());
+            batch.rollups.extend(
+                series[series.len() - new..]
+                    .iter()
+                    .map(|bucket| (*resolution, battery.as_deref(), bucket)),
+            );
+            if let Some(first) = series.first() {
+                batch
+                    .keep_rollups_from
+                    .push((*resolution, battery.as_deref(), first.start));
+            }
         }
+        let new_sleeps = self.pending.sleeps.min(self.sleeps.len());
+        batch
+            .sleeps
+            .extend(&self.sleeps[self.sleeps.len() - new_sleeps..]);
+        batch.keep_sleeps_from = self.sleeps.first().map(|s| s.start);
+        batch.keep_sessions_from.push((
+            SessionKind::Charge,
+            self.charge_sessions.first().map(|s| s.start_time),
+        ));
+        batch.keep_sessions_from.push((
+            SessionKind::Discharge,
+            self.discharge_sessions.first().map(|s| s.start_time),
+        ));
+        batch
     }
 
-    fn data_path() -> PathBuf {
+    fn json_path() -> PathBuf {
         dirs::data_dir()
             .unwrap_or_else(|| PathBuf::from("."))
             .join("bathis")
             .join("history.json")
     }
 
-    /// Add a new sample and update charge session tracking
+    /// Add a new sample and update charge session tracking.
+    /// Samples tagged with a battery name go to that battery's series and
+    /// do not affect charge sessions, which follow the combined pack.
     pub fn add_sample(&mut self, sample: BatterySample) {
-        // Track charge sessions
+        self.add_to_rollups(&sample);
+        if let Some(name) = sample.battery.clone() {
+            *self
+                .pending
+                .battery_samples
+                .entry(name.clone())
+                .or_default() += 1;
+            let series = self.battery_samples.entry(name).or_default();
+            series.push(sample);
+            truncate_samples(series);
+            return;
+        }
+
+        self.detect_sleep(&sample);
+
+        // Track charge and discharge sessions
+        let completion_target = self.completion_target();
         match sample.status {
             BatteryStatus::Charging => {
+                self.close_discharge_session(sample.timestamp);
                 if self.active_session.is_none() {
                     // Start a new charge session
                     self.active_session = Some(ChargeSession {
@@ -82,52 +774,702 @@ impl History {
                         end_capacity: sample.capacity,
                         samples: vec![sample.clone()],
                         completed: false,
+                        charger: describe_adapters(&sample.adapters),
                     });
                 } else if let Some(ref mut session) = self.active_session {
+                    if session.charger.is_none() {
+                        session.charger = describe_adapters(&sample.adapters);
+                    }
                     session.end_capacity = sample.capacity;
                     session.end_time = Some(sample.timestamp);
                     session.samples.push(sample.clone());
 
-                    // Check if reached 90%+
-                    if sample.capacity >= 90.0 && !session.completed {
+                    // Check if reached the target (90%+ unless configured otherwise)
+                    if sample.capacity >= completion_target && !session.completed {
                         session.completed = true;
                     }
                 }
             }
-            _ => {
-                // Not charging — close active session if exists
-                if let Some(mut session) = self.active_session.take() {
-                    session.end_time = Some(sample.timestamp);
-                    if session.completed {
-                        self.charge_sessions.push(session);
-                        // Keep only last 2 completed sessions
-                        while self.charge_sessions.len() > 2 {
-                            self.charge_sessions.remove(0);
-                        }
+            BatteryStatus::Discharging => {
+                self.close_charge_session(sample.timestamp);
+                match self.active_discharge {
+                    None => {
+                        // Unplugged — start a new discharge session
+                        self.active_discharge = Some(DischargeSession {
+                            start_time: sample.timestamp,
+                            end_time: None,
+                            start_capacity: sample.capacity,
+                            end_capacity: sample.capacity,
+                            samples: vec![sample.clone()],
+                        });
+                    }
+                    Some(ref mut session) => {
+                        session.end_capacity = sample.capacity;
+                        session.end_time = Some(sample.timestamp);
+                        session.samples.push(sample.clone());
                     }
-                    // If not completed (didn't reach 90%), just discard
                 }
             }
+            _ => {
+                // Plugged in but not charging (full, threshold reached, ...)
+                self.close_charge_session(sample.timestamp);
+                self.close_discharge_session(sample.timestamp);
+            }
         }
 
+        self.pending.samples += 1;
         self.samples.push(sample);
+        truncate_samples(&mut self.samples);
+    }
+
+    /// Record a sleep if `sample` follows the previous pack sample by more
+    /// than `sleep_gap`, and drop sleeps older than the longest rollup tier
+    fn detect_sleep(&mut self, sample: &BatterySample) {
+        let observed = std::mem::replace(&mut self.sampling, true);
+        let Some(previous) = self.samples.last() else {
+            return;
+        };
+        if !observed || sample.timestamp - previous.timestamp <= self.sleep_gap {
+            return;
+        }
+        self.sleeps.push(SleepPeriod {
+            start: previous.timestamp,
+            end: sample.timestamp,
+            start_capacity: previous.capacity,
+            end_capacity: sample.capacity,
+            on_battery: previous.status == BatteryStatus::Discharging
+                && sample.status == BatteryStatus::Discharging,
+        });
+        self.pending.sleeps += 1;
+        let cutoff = sample.timestamp - Resolution::QuarterHour.retention();
+        let expired = self.sleeps.partition_point(|s| s.start < cutoff);
+        self.sleeps.drain(..expired);
+    }
+
+    /// Average drain over the sleeps spent on battery, if there were any
+    pub fn sleep_summary(&self) -> Option<SleepSummary> {
+        let on_battery = self.sleeps.iter().filter(|s| s.on_battery);
+        let (count, hours, lost) = on_battery.fold((0, 0.0, 0.0), |(n, h, l), s| {
+            (n + 1, h + s.hours(), l + s.capacity_lost())
+        });
+        (hours > 0.0).then(|| SleepSummary {
+            count,
+            hours,
+            drain_per_hour: lost / hours,
+        })
+    }
+
+    /// Fold a sample into the rollup tiers of its series
+    fn add_to_rollups(&mut self, sample: &BatterySample) {
+        let previous = self
+            .samples_for(sample.battery.as_deref())
+            .last()
+            .map(|s| s.timestamp);
+        for resolution in Resolution::ROLLUPS {
+            let key = (resolution, sample.battery.clone());
+            let series = self.rollups.entry(key.clone()).or_default();
+            let started = rollup::add_sample(series, resolution, sample, previous);
+            let pending = self.pending.rollups.entry(key).or_default();
+            // A new bucket, or the first change to the newest one since the last save
+            if started || *pending == 0 {
+                *pending += 1;
+            }
+        }
+    }
+
+    /// Pick up the sessions that were in progress when the history was saved.
+    /// A session whose last sample is within `RESUME_WINDOW_SECS` of `now`
+    /// carries on with the next sample; an older one is closed at its last
+    /// sample, since nothing is known about the time in between.
+    pub fn resume_sessions(&mut self, now: DateTime<Local>) {
+        let is_stale = |samples: &[BatterySample]| {
+            samples
+                .last()
+                .is_none_or(|s| (now - s.timestamp).num_seconds() > RESUME_WINDOW_SECS)
+        };
+        if let Some(session) = &self.active_session
+            && is_stale(&session.samples)
+        {
+            let end = session
+                .samples
+                .last()
+                .map_or(session.start_time, |s| s.timestamp);
+            self.close_charge_session(end);
+        }
+        if let Some(session) = &self.active_discharge
+            && is_stale(&session.samples)
+        {
+            let end = session
+                .samples
+                .last()
+                .map_or(session.start_time, |s| s.timestamp);
+            self.close_discharge_session(end);
+        }
+    }
+
+    /// Finish the sessions in progress at their last sample, for a recorder
+    /// that is stopping
+    pub fn close_sessions(&mut self) {
+        let last = |samples: &[BatterySample]| samples.last().map(|s| s.timestamp);
+        if let Some(end) = self.active_session.as_ref().and_then(|s| last(&s.samples)) {
+            self.close_charge_session(end);
+        }
+        if let Some(end) = self
+            .active_discharge
+            .as_ref()
+            .and_then(|s| last(&s.samples))
+        {
+            self.close_discharge_session(end);
+        }
+    }
+
+    /// Finish the active charge session, if any, at `end`
+    fn close_charge_session(&mut self, end: DateTime<Local>) {
+        if let Some(mut session) = self.active_session.take() {
+            session.end_time = Some(end);
+            // A single sample has no duration or curve worth keeping
+            if session.samples.len() > 1 {
+                session.completed = session.completed && self.meets_rules(&session);
+                self.pending
+                    .closed_sessions
+                    .push((SessionKind::Charge, session.start_time));
+                self.charge_sessions.push(session);
+                self.trim_sessions();
+            }
+        }
+    }
+
+    /// Finish the active discharge session, if any, at `end`
+    fn close_discharge_session(&mut self, end: DateTime<Local>) {
+        if let Some(mut session) = self.active_discharge.take() {
+            session.end_time = Some(end);
+            if session.samples.len() > 1 {
+                self.pending
+                    .closed_sessions
+                    .push((SessionKind::Discharge, session.start_time));
+                self.discharge_sessions.push(session);
+                self.trim_sessions();
+            }
+        }
+    }
 
-        // Limit total sample count to avoid unbounded growth
-        // Keep last ~48h at 5s intervals = ~34560 samples
-        const MAX_SAMPLES: usize = 40000;
-        if self.samples.len() > MAX_SAMPLES {
-            let drain_count = self.samples.len() - MAX_SAMPLES;
-            self.samples.drain(..drain_count);
+    /// Capacity at which a charge session counts as completed
+    pub fn completion_target(&self) -> f64 {
+        if let Some(target) = self.rules.target_percent {
+            return target;
+        }
+        match self.charge_end_threshold {
+            // Firmware usually stops a point or so short of the threshold
+            Some(end) => f64::from(end) - 1.0,
+            None => 90.0,
         }
     }
 
+    /// Check the minimum gain and duration rules for a finished session
+    fn meets_rules(&self, session: &ChargeSession) -> bool {
+        let delta = session.end_capacity - session.start_capacity;
+        let duration = session
+            .end_time
+            .map(|e| (e - session.start_time).num_seconds())
+            .unwrap_or(0);
+        delta >= self.rules.min_delta_percent && duration >= self.rules.min_duration_secs as i64
+    }
+
+    /// Apply the configured session retention to charge and discharge sessions
+    fn trim_sessions(&mut self) {
+        trim_by_retention(&mut self.charge_sessions, &self.rules);
+        trim_by_retention(&mut self.discharge_sessions, &self.rules);
+    }
+
     /// Get all samples for display (including current + loaded history)
     pub fn all_samples(&self) -> &[BatterySample] {
         &self.samples
     }
 
-    /// Get charge sessions for display
-    pub fn completed_sessions(&self) -> &[ChargeSession] {
+    /// Samples for one battery, or the combined pack when `battery` is `None`
+    pub fn samples_for(&self, battery: Option<&str>) -> &[BatterySample] {
+        match battery {
+            Some(name) => self
+                .battery_samples
+                .get(name)
+                .map(Vec::as_slice)
+                .unwrap_or(&[]),
+            None => &self.samples,
+        }
+    }
+
+    /// Rollups of one series (None = pack) at `resolution`, oldest first
+    pub fn rollups_for(&self, resolution: Resolution, battery: Option<&str>) -> &[Rollup] {
+        self.rollups
+            .get(&(resolution, battery.map(str::to_string)))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Names of the individual batteries with samples or rollups
+    pub fn battery_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .battery_samples
+            .keys()
+            .chain(
+                self.rollups
+                    .keys()
+                    .filter_map(|(_, battery)| battery.as_ref()),
+            )
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Oldest point of a series in any tier
+    pub fn first_timestamp(&self, battery: Option<&str>) -> Option<DateTime<Local>> {
+        Resolution::ROLLUPS
+            .iter()
+            .filter_map(|&r| self.rollups_for(r, battery).first().map(|b| b.start))
+            .chain(self.samples_for(battery).first().map(|s| s.timestamp))
+            .min()
+    }
+
+    /// Finest resolution that has data back to `from` and draws the
+    /// `span_secs` after it with at most `MAX_CHART_POINTS` points
+    pub fn resolution_for(
+        &self,
+        battery: Option<&str>,
+        from: DateTime<Local>,
+        span_secs: f64,
+    ) -> Resolution {
+        let until = from + chrono::Duration::milliseconds((span_secs * 1000.0) as i64);
+        let fits = |points: Option<usize>| points.is_some_and(|n| n <= MAX_CHART_POINTS);
+
+        let raw = self.samples_for(battery);
+        if fits(points_between(raw, |s| s.timestamp, from, until)) {
+            return Resolution::Raw;
+        }
+        Resolution::ROLLUPS
+            .into_iter()
+            .find(|&r| {
+                let buckets = self.rollups_for(r, battery);
+                fits(points_between(buckets, |b| b.start, from, until))
+            })
+            .unwrap_or(Resolution::QuarterHour)
+    }
+
+    /// Get all finished charge sessions (completed and partial), oldest first
+    pub fn sessions(&self) -> &[ChargeSession] {
         &self.charge_sessions
     }
+
+    /// Number of finished sessions of `kind`
+    pub fn session_count(&self, kind: SessionKind) -> usize {
+        match kind {
+            SessionKind::Charge => self.charge_sessions.len(),
+            SessionKind::Discharge => self.discharge_sessions.len(),
+        }
+    }
+
+    /// Index of the finished session of `kind` that started at `start`
+    pub fn session_index(&self, kind: SessionKind, start: DateTime<Local>) -> Option<usize> {
+        match kind {
+            SessionKind::Charge => self
+                .charge_sessions
+                .iter()
+                .position(|s| s.start_time == start),
+            SessionKind::Discharge => self
+                .discharge_sessions
+                .iter()
+                .position(|s| s.start_time == start),
+        }
+    }
+
+    /// Finished session of `kind` at `idx`
+    pub fn session(&self, kind: SessionKind, idx: usize) -> Option<&dyn SessionStats> {
+        match kind {
+            SessionKind::Charge => self
+                .charge_sessions
+                .get(idx)
+                .map(|s| s as &dyn SessionStats),
+            SessionKind::Discharge => self
+                .discharge_sessions
+                .get(idx)
+                .map(|s| s as &dyn SessionStats),
+        }
+    }
+}
+
+/// Move a JSON file that did not parse to `<name>.json.corrupt`, so it is
+/// not tried again. Returns the warning to show.
+pub fn set_aside(json_path: &Path, source: serde_json::Error) -> String {
+    let error = Error::Json {
+        path: json_path.to_path_buf(),
+        source,
+    };
+    let aside = json_path.with_extension("json.corrupt");
+    match fs::rename(json_path, &aside) {
+        Ok(()) => format!("{error}; kept it as {}", aside.display()),
+        Err(e) => format!("{error}; cannot move it aside ({e})"),
+    }
+}
+
+/// Drop sessions past `retention_days`, then the oldest beyond `max_sessions`
+fn trim_by_retention<S: SessionStats>(sessions: &mut Vec<S>, rules: &SessionRules) {
+    if let Some(days) = rules.retention_days {
+        let cutoff = Local::now() - chrono::Duration::days(i64::from(days));
+        sessions.retain(|s| s.start_time() >= cutoff);
+    }
+    if let Some(max) = rules.max_sessions
+        && sessions.len() > max
+    {
+        let excess = sessions.len() - max;
+        sessions.drain(..excess);
+    }
+}
+
+/// Number of points of `series` from `from` to `until`, counted since
+/// sampling intervals vary. `None` if the series does not reach back to `from`.
+fn points_between<T>(
+    series: &[T],
+    time: impl Fn(&T) -> DateTime<Local>,
+    from: DateTime<Local>,
+    until: DateTime<Local>,
+) -> Option<usize> {
+    if series.first().is_none_or(|p| time(p) > from) {
+        return None;
+    }
+    let first = series.partition_point(|p| time(p) < from);
+    let last = series.partition_point(|p| time(p) <= until);
+    Some(last.saturating_sub(first))
+}
+
+/// Keep the last 48 h of raw samples; the rollups cover what is older
+fn truncate_samples(samples: &mut Vec<BatterySample>) {
+    if let Some(newest) = samples.last().map(|s| s.timestamp) {
+        let cutoff = newest - Resolution::Raw.retention();
+        let expired = samples.partition_point(|s| s.timestamp < cutoff);
+        samples.drain(..expired);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, temp_dir};
+
+    fn sample(minute: i64, capacity: f64, status: BatteryStatus) -> BatterySample {
+        test_support::sample(minute * 60, capacity, 0.0, status)
+    }
+
+    /// Charge from `from` to `to` over `minutes`, then unplug
+    fn charge(history: &mut History, start_minute: i64, from: f64, to: f64, minutes: i64) {
+        for m in 0..=minutes {
+            let capacity = from + (to - from) * m as f64 / minutes as f64;
+            history.add_sample(sample(start_minute + m, capacity, BatteryStatus::Charging));
+        }
+        history.add_sample(sample(
+            start_minute + minutes + 1,
+            to,
+            BatteryStatus::Discharging,
+        ));
+    }
+
+    #[test]
+    fn short_top_up_is_kept_as_partial() {
+        let mut history = History::new();
+        charge(&mut history, 0, 60.0, 75.0, 20);
+        charge(&mut history, 100, 30.0, 95.0, 60);
+
+        let sessions = history.sessions();
+        assert_eq!(sessions.len(), 2);
+        assert!(!sessions[0].completed);
+        assert!(sessions[1].completed);
+    }
+
+    #[test]
+    fn configured_rules_decide_completion() {
+        let mut history = History::new();
+        history.rules = SessionRules {
+            target_percent: Some(80.0),
+            min_delta_percent: 20.0,
+            min_duration_secs: 30 * 60,
+            ..SessionRules::default()
+        };
+        charge(&mut history, 0, 50.0, 82.0, 40); // meets every rule
+        charge(&mut history, 100, 70.0, 85.0, 40); // gains only 15 points
+        charge(&mut history, 200, 40.0, 85.0, 20); // too short
+
+        let completed: Vec<bool> = history.sessions().iter().map(|s| s.completed).collect();
+        assert_eq!(completed, vec![true, false, false]);
+    }
+
+    #[test]
+    fn max_sessions_drops_the_oldest() {
+        let mut history = History::new();
+        history.rules.max_sessions = Some(3);
+        for i in 0..5 {
+            charge(&mut history, i * 100, 20.0 + i as f64, 95.0, 60);
+        }
+
+        let starts: Vec<f64> = history
+            .sessions()
+            .iter()
+            .map(|s| s.start_capacity)
+            .collect();
+        assert_eq!(starts, vec![22.0, 23.0, 24.0]);
+    }
+
+    #[test]
+    fn unplug_to_plug_in_is_a_discharge_session() {
+        let mut history = History::new();
+        for m in 0..=60 {
+            let capacity = 80.0 - 30.0 * m as f64 / 60.0;
+            let mut s = sample(m, capacity, BatteryStatus::Discharging);
+            s.energy_full_wh = 50.0;
+            s.energy_now_wh = capacity / 100.0 * 50.0;
+            s.power_watts = if m == 30 { -20.0 } else { -10.0 };
+            history.add_sample(s);
+        }
+        history.add_sample(sample(61, 50.0, BatteryStatus::Charging));
+
+        assert_eq!(history.session_count(SessionKind::Discharge), 1);
+        assert!(history.active_discharge.is_none());
+        let session = history.session(SessionKind::Discharge, 0).unwrap();
+        assert_eq!(session.duration_secs(), 61.0 * 60.0);
+        assert!((session.energy_wh() - 15.0).abs() < 1e-9);
+        assert!((session.average_watts() - 610.0 / 60.0).abs() < 1e-9);
+        assert_eq!(session.peak_watts(), 20.0);
+    }
+
+    #[test]
+    fn saved_session_resumes_after_quick_restart() {
+        let mut history = History::new();
+        for m in 0..=10 {
+            history.add_sample(sample(m, 40.0 + m as f64, BatteryStatus::Charging));
+        }
+        let data = serde_json::to_string(&history).unwrap();
+
+        let mut restored: History = serde_json::from_str(&data).unwrap();
+        restored.resume_sessions(sample(12, 0.0, BatteryStatus::Unknown).timestamp);
+        restored.add_sample(sample(12, 52.0, BatteryStatus::Charging));
+        restored.add_sample(sample(13, 52.0, BatteryStatus::Discharging));
+
+        let sessions = restored.sessions();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].start_capacity, 40.0);
+        assert_eq!(sessions[0].end_capacity, 52.0);
+    }
+
+    #[test]
+    fn stale_saved_session_closes_at_last_sample() {
+        let mut history = History::new();
+        for m in 0..=10 {
+            history.add_sample(sample(m, 40.0 + m as f64, BatteryStatus::Charging));
+        }
+        let data = serde_json::to_string(&history).unwrap();
+
+        let mut restored: History = serde_json::from_str(&data).unwrap();
+        restored.resume_sessions(sample(180, 0.0, BatteryStatus::Unknown).timestamp);
+
+        assert!(restored.active_session.is_none());
+        let sessions = restored.sessions();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(
+            sessions[0].end_time,
+            Some(sample(10, 0.0, BatteryStatus::Unknown).timestamp)
+        );
+        assert_eq!(sessions[0].end_capacity, 50.0);
+    }
+
+    #[test]
+    fn stopping_closes_sessions_at_their_last_sample() {
+        let mut history = History::new();
+        for m in 0..=10 {
+            history.add_sample(sample(m, 40.0 + m as f64, BatteryStatus::Charging));
+        }
+        history.close_sessions();
+
+        assert!(history.active_session.is_none());
+        let sessions = history.sessions();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(
+            sessions[0].end_time,
+            Some(sample(10, 0.0, BatteryStatus::Unknown).timestamp)
+        );
+    }
+
+    #[test]
+    fn saves_incrementally_and_reloads_from_the_store() {
+        let dir = temp_dir("store");
+        let db = dir.join("history.db");
+        let json = dir.join("history.json");
+
+        let mut history = History::open(Store::open(&db).unwrap(), &json);
+        charge(&mut history, 0, 40.0, 95.0, 30);
+        history.save().unwrap();
+        for m in 40..50 {
+            history.add_sample(sample(m, 60.0, BatteryStatus::Charging));
+        }
+        history.save().unwrap();
+        history.add_sample(sample(50, 61.0, BatteryStatus::Charging));
+        history.save().unwrap();
+
+        let restored = History::open(Store::open(&db).unwrap(), &json);
+        assert_eq!(restored.samples.len(), history.samples.len());
+        assert_eq!(restored.sessions().len(), 1);
+        assert_eq!(restored.sessions()[0].samples.len(), 31);
+        assert!(restored.sessions()[0].completed);
+        let active = restored.active_session.as_ref().unwrap();
+        assert_eq!(active.samples.len(), 11);
+        assert_eq!(active.end_capacity, 61.0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn gaps_become_sleeps_with_their_drain() {
+        let dir = temp_dir("sleeps");
+        let db = dir.join("history.db");
+        let json = dir.join("history.json");
+
+        let mut history = History::open(Store::open(&db).unwrap(), &json);
+        for m in 0..=10 {
+            history.add_sample(sample(m, 80.0, BatteryStatus::Discharging));
+        }
+        // Two hours asleep on battery, then half an hour asleep on AC
+        history.add_sample(sample(130, 76.0, BatteryStatus::Discharging));
+        history.add_sample(sample(131, 76.0, BatteryStatus::Charging));
+        history.add_sample(sample(161, 80.0, BatteryStatus::Charging));
+        history.save().unwrap();
+
+        assert_eq!(history.sleeps.len(), 2);
+        assert_eq!(history.sleeps[0].capacity_lost(), 4.0);
+        assert!(!history.sleeps[1].on_battery);
+        let summary = history.sleep_summary().unwrap();
+        assert_eq!(summary.count, 1);
+        assert_eq!(summary.hours, 2.0);
+        assert_eq!(summary.drain_per_hour, 2.0);
+
+        // The gap up to the first sample after loading may be time bathis was not running
+        let mut restored = History::open(Store::open(&db).unwrap(), &json);
+        assert_eq!(restored.sleeps, history.sleeps);
+        restored.add_sample(sample(600, 70.0, BatteryStatus::Discharging));
+        restored.add_sample(sample(601, 70.0, BatteryStatus::Discharging));
+        assert_eq!(restored.sleeps.len(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// A discharging sample `seconds` into the test period
+    fn sample_at(seconds: i64, capacity: f64, power_watts: f64) -> BatterySample {
+        let mut s = sample(0, capacity, BatteryStatus::Discharging);
+        s.timestamp += chrono::Duration::seconds(seconds);
+        s.power_watts = power_watts;
+        s
+    }
+
+    /// Three samples in the first minute, then one every half hour for three days
+    fn three_days() -> Vec<BatterySample> {
+        let mut samples = vec![
+            sample_at(0, 50.0, -5.0),
+            sample_at(20, 52.0, -10.0),
+            sample_at(40, 51.0, -6.0),
+        ];
+        for half_hour in 1..=3 * 48 {
+            samples.push(sample_at(half_hour * 1800, 40.0, -8.0));
+        }
+        samples
+    }
+
+    #[test]
+    fn old_samples_survive_as_rollups() {
+        let dir = temp_dir("rollups");
+        let db = dir.join("history.db");
+        let json = dir.join("history.json");
+
+        let mut history = History::open(Store::open(&db).unwrap(), &json);
+        for (i, sample) in three_days().into_iter().enumerate() {
+            history.add_sample(sample);
+            if i % 50 == 0 {
+                history.save().unwrap();
+            }
+        }
+        history.save().unwrap();
+
+        // Raw samples only reach back 48 h
+        let newest = history.samples.last().unwrap().timestamp;
+        let oldest = history.samples.first().unwrap().timestamp;
+        assert_eq!(newest - oldest, Resolution::Raw.retention());
+
+        let minute = history.rollups_for(Resolution::Minute, None);
+        assert_eq!(minute.len(), 1 + 3 * 48);
+        assert_eq!(minute[0].count, 3);
+        assert_eq!(
+            (minute[0].capacity.min, minute[0].capacity.max),
+            (50.0, 52.0)
+        );
+        // Weighted by the time each sample stands for: 1 s, 20 s and 20 s
+        let weighted = (50.0 + 52.0 * 20.0 + 51.0 * 20.0) / 41.0;
+        assert!((minute[0].capacity.avg - weighted).abs() < 1e-9);
+        assert_eq!(
+            (minute[0].power_watts.min, minute[0].power_watts.max),
+            (-10.0, -5.0)
+        );
+        let weighted = (-5.0 - 10.0 * 20.0 - 6.0 * 20.0) / 41.0;
+        assert!((minute[0].power_watts.avg - weighted).abs() < 1e-9);
+        assert_eq!(
+            history.rollups_for(Resolution::QuarterHour, None)[0].count,
+            3
+        );
+
+        let restored = History::open(Store::open(&db).unwrap(), &json);
+        assert_eq!(restored.samples.len(), history.samples.len());
+        for resolution in Resolution::ROLLUPS {
+            assert_eq!(
+                restored.rollups_for(resolution, None),
+                history.rollups_for(resolution, None)
+            );
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn chart_resolution_follows_the_visible_range() {
+        // A sample every 30 s for three days
+        let mut history = History::new();
+        for i in 0..=3 * 24 * 120 {
+            history.add_sample(sample_at(i * 30, 50.0, -8.0));
+        }
+        let newest = history.samples.last().unwrap().timestamp;
+        let hours = |h: i64| chrono::Duration::hours(h);
+
+        let at = |from, span: chrono::Duration| {
+            history.resolution_for(None, from, span.num_seconds() as f64)
+        };
+        assert_eq!(at(newest - hours(1), hours(1)), Resolution::Raw);
+        // Zoomed out too far for raw samples
+        assert_eq!(at(newest - hours(40), hours(40)), Resolution::Minute);
+        // Before the oldest raw sample
+        assert_eq!(at(newest - hours(60), hours(1)), Resolution::Minute);
+        assert_eq!(at(newest - hours(72), hours(72)), Resolution::QuarterHour);
+    }
+
+    #[test]
+    fn json_history_is_imported_once() {
+        let dir = temp_dir("import");
+        let db = dir.join("history.db");
+        let json = dir.join("history.json");
+
+        let mut old = History::new();
+        charge(&mut old, 0, 40.0, 95.0, 30);
+        fs::write(&json, serde_json::to_string(&old).unwrap()).unwrap();
+
+        let imported = History::open(Store::open(&db).unwrap(), &json);
+        assert_eq!(imported.samples.len(), 32);
+        assert_eq!(imported.sessions().len(), 1);
+        assert!(!json.exists());
+        assert!(dir.join("history.json.imported").exists());
+
+        let reopened = History::open(Store::open(&db).unwrap(), &json);
+        assert_eq!(reopened.samples.len(), 32);
+        assert_eq!(reopened.sessions().len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,68 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Advisory lock making one bathis process the only writer of the history
+/// database. The OS releases it when the process exits, even after a crash.
+#[derive(Debug)]
+pub struct WriterLock {
+    _file: File,
+}
+
+impl WriterLock {
+    /// Try to become the writer. `Ok(None)` means another process already is.
+    pub fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {
+                // Record who holds the lock, for the messages of the others
+                file.set_len(0)?;
+                write!(file, "{}", std::process::id())?;
+                Ok(Some(WriterLock { _file: file }))
+            }
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+
+    /// Process id of the current writer, as recorded in the lockfile
+    pub fn holder(path: &Path) -> Option<u32> {
+        fs::read_to_string(path).ok()?.trim().parse().ok()
+    }
+
+    pub fn lock_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("bathis")
+            .join("writer.lock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn only_one_writer_at_a_time() {
+        let dir = temp_dir("lock");
+        let path = dir.join("writer.lock");
+
+        let first = WriterLock::try_acquire(&path).unwrap();
+        assert!(first.is_some());
+        assert!(WriterLock::try_acquire(&path).unwrap().is_none());
+        assert_eq!(WriterLock::holder(&path), Some(std::process::id()));
+
+        drop(first);
+        assert!(WriterLock::try_acquire(&path).unwrap().is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
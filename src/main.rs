@@ -1,59 +1,333 @@
 mod app;
+mod bar;
 mod battery;
+mod cli;
+mod config;
+mod error;
+mod estimate;
+mod format;
+mod health;
 mod history;
+mod lock;
+mod metrics;
+mod recorder;
+mod report;
+mod rollup;
+mod signals;
+mod source;
+mod store;
+#[cfg(test)]
+mod test_support;
 mod ui;
 
-use std::env;
-use std::io;
+use std::io::{self, Write};
 use std::thread;
 use std::time::{Duration, Instant};
 
+use chrono::Local;
 use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 use ratatui::DefaultTerminal;
 
 use app::App;
-use battery::BatteryReader;
+use bar::Levels;
+use battery::{BatteryReader, BatterySample};
+use clap::Parser;
+use cli::{BarFormat, Cli, Command, RecordArgs, ThresholdAction};
+use config::Config;
+use error::Error;
+use estimate::ChargeCurve;
+use health::HealthLog;
+use history::{History, SessionKind};
+use lock::WriterLock;
+use metrics::{Metrics, MetricsServer};
+use recorder::{RecorderClient, RecorderServer, RecorderStatus};
+use report::{Stats, Status};
+use signals::Signals;
+use source::{BatterySource, ReplaySource, SyntheticSource};
 
-const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// Cycle count and capacity change slowly; re-read them hourly
+const HEALTH_INTERVAL: Duration = Duration::from_secs(3600);
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+/// Longest the recorder waits before noticing a signal
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(200);
 
-    if args.iter().any(|a| a == "--help" || a == "-h") {
-        println!("Usage: bathis [OPTIONS]");
-        println!();
-        println!("Options:");
-        println!("  --record    Run headless, sampling battery to history without TUI");
-        println!("  -h, --help  Show this help");
-        return Ok(());
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run_command(&cli) {
+        eprintln!("bathis: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run_command(cli: &Cli) -> io::Result<()> {
+    match *cli.selected_command() {
+        Command::Tui => {
+            let config = load_config(cli)?;
+            // With a recorder running, take its samples instead of reading sysfs as well
+            let source = match attach_recorder(cli) {
+                Some(client) => Box::new(client),
+                None => open_source(cli)?,
+            };
+
+            let mut terminal = ratatui::init();
+            let result = run(&mut terminal, source, config, cli);
+            ratatui::restore();
+            result
+        }
+        Command::Record(ref args) => {
+            let mut source = open_source(cli)?;
+            if !source.is_live() {
+                eprintln!(
+                    "bathis: record only works with real batteries, not --replay/--synthetic"
+                );
+                std::process::exit(2);
+            }
+            let status = run_headless(source.as_mut(), load_config(cli)?, cli, args)?;
+            std::process::exit(status);
+        }
+        Command::Status { json } => {
+            let mut source = match attach_recorder(cli) {
+                Some(client) => Box::new(client),
+                None => open_source(cli)?,
+            };
+            let history = if source.is_live() {
+                History::load_recent(Local::now() - report::RECENT_SAMPLES)
+            } else {
+                History::new()
+            };
+            let reading = source.sample()?;
+            let curve = ChargeCurve::learn(history.sessions());
+            let status = Status::new(source.as_ref(), reading, history.samples_for(None), &curve)
+                .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no battery reading available")
+            })?;
+            print!("{}", status.render(json)?);
+            Ok(())
+        }
+        Command::Sessions {
+            discharge,
+            limit,
+            json,
+        } => {
+            let kind = if discharge {
+                SessionKind::Discharge
+            } else {
+                SessionKind::Charge
+            };
+            print!(
+                "{}",
+                report::sessions(&load_history(cli)?, kind, limit, json)?
+            );
+            Ok(())
+        }
+        Command::Export {
+            resolution,
+            ref battery,
+            json,
+        } => {
+            let history = load_history(cli)?;
+            let out = report::export(&history, resolution.into(), battery.as_deref(), json)?;
+            print!("{out}");
+            Ok(())
+        }
+        Command::Stats { json } => {
+            let history = load_history(cli)?;
+            let health_log = HealthLog::load_following();
+            if let Some(warning) = &health_log.load_warning {
+                eprintln!("bathis: {warning}");
+            }
+            let stats = Stats::new(&history, &health_log);
+            print!("{}", stats.render(json)?);
+            Ok(())
+        }
+        Command::Bar {
+            format,
+            watch,
+            warning,
+            critical,
+        } => run_bar(cli, format, Levels { warning, critical }, watch),
+        Command::Threshold { action, json } => run_threshold(action, &open_reader(cli)?, json),
+    }
+}
+
+/// `bathis bar`: print one update, or with `--watch` one after every sample
+/// until the bar closes the pipe
+fn run_bar(cli: &Cli, format: BarFormat, levels: Levels, watch: bool) -> io::Result<()> {
+    if levels.critical > levels.warning {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "--critical ({}%) must not be above --warning ({}%)",
+                levels.critical, levels.warning
+            ),
+        ));
+    }
+    let config = load_config(cli)?;
+    let mut source = match attach_recorder(cli) {
+        Some(client) => Box::new(client),
+        None => open_source(cli)?,
+    };
+    let history = if source.is_live() {
+        History::load_recent(Local::now() - report::RECENT_SAMPLES)
+    } else {
+        History::new()
+    };
+    let curve = ChargeCurve::learn(history.sessions());
+    // Earlier pack samples, for smoothing the time left
+    let mut earlier = history.samples_for(None).to_vec();
+    let mut stdout = io::stdout().lock();
+
+    loop {
+        let update = match source.sample() {
+            Ok(reading) => Status::new(source.as_ref(), reading, &earlier, &curve).map(|status| {
+                let update = (
+                    bar::render(&status, format, levels, watch),
+                    bar::is_critical(&status, levels),
+                );
+                let cutoff = status.pack.timestamp - report::RECENT_SAMPLES;
+                earlier.retain(|s| s.timestamp >= cutoff);
+                earlier.push(status.pack);
+                update
+            }),
+            Err(e) => Some((bar::render_error(&e.to_string(), format, watch), false)),
+        };
+
+        if !watch {
+            let (text, critical) = update.unwrap_or_else(|| {
+                let error = "no battery reading available";
+                (bar::render_error(error, format, false), false)
+            });
+            print!("{text}");
+            if critical && format == BarFormat::I3blocks {
+                std::process::exit(bar::I3BLOCKS_URGENT);
+            }
+            return Ok(());
+        }
+        if let Some((text, _)) = update {
+            match stdout
+                .write_all(text.as_bytes())
+                .and_then(|()| stdout.flush())
+            {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+        }
+        // The recorder went away: read the batteries directly from now on
+        if source.detached() {
+            source = Box::new(open_reader(cli)?);
+        }
+        thread::sleep(config.sampling.interval_after(earlier.last()));
+    }
+}
+
+/// Read the config file, then apply the sampling options given on the command line
+fn load_config(cli: &Cli) -> io::Result<Config> {
+    let mut config = Config::load(cli.config.as_deref());
+    if let Some(secs) = cli.source.interval {
+        config.sampling.interval_secs = secs;
+    }
+    if cli.source.adaptive {
+        config.sampling.adaptive = true;
+    }
+    Ok(config)
+}
+
+/// The saved history, read without taking over writing it
+fn load_history(cli: &Cli) -> io::Result<History> {
+    if cli.source.other_source() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "this command reads the saved history; --replay, --synthetic and --sysfs-root do not apply",
+        ));
+    }
+    let config = load_config(cli)?;
+    let mut history = History::load_following();
+    history.rules = config.sessions;
+    if let Some(warning) = &history.load_warning {
+        eprintln!("bathis: {warning}");
+    }
+    Ok(history)
+}
+
+/// Build the battery source selected on the command line (sysfs by default)
+fn open_source(cli: &Cli) -> io::Result<Box<dyn BatterySource>> {
+    if let Some(path) = &cli.source.replay {
+        return Ok(Box::new(ReplaySource::open(path, cli.source.speed)?));
+    }
+    if cli.source.synthetic {
+        return Ok(Box::new(SyntheticSource::new(cli.source.speed)));
     }
 
-    let reader = BatteryReader::new().expect("No battery found in /sys/class/power_supply/");
+    Ok(Box::new(open_reader(cli)?))
+}
 
-    if args.iter().any(|a| a == "--record") {
-        return run_headless(reader);
+/// Connect to a running `bathis record`, unless another source was asked for
+fn attach_recorder(cli: &Cli) -> Option<RecorderClient> {
+    if cli.source.other_source() {
+        return None;
     }
+    RecorderClient::connect(&RecorderServer::socket_path()).ok()
+}
 
-    let battery_name = reader.battery_name();
-    let mut terminal = ratatui::init();
-    let result = run(&mut terminal, reader, battery_name);
-    ratatui::restore();
-    result
+/// Open the sysfs reader, honouring `--sysfs-root`
+fn open_reader(cli: &Cli) -> Result<BatteryReader, Error> {
+    match &cli.source.sysfs_root {
+        Some(root) => BatteryReader::with_root(root),
+        None => BatteryReader::new(),
+    }
+}
+
+/// `bathis threshold [show]` and `bathis threshold set <START> <END>`
+fn run_threshold(
+    action: Option<ThresholdAction>,
+    reader: &BatteryReader,
+    json: bool,
+) -> io::Result<()> {
+    match action.unwrap_or(ThresholdAction::Show) {
+        ThresholdAction::Show => {
+            let thresholds = reader.thresholds();
+            match &thresholds {
+                _ if json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&thresholds).map_err(io::Error::other)?
+                ),
+                Some(thresholds) => println!("{thresholds}"),
+                None => println!("This battery does not expose charge thresholds"),
+            }
+            Ok(())
+        }
+        ThresholdAction::Set { start, end } => {
+            let batteries = reader.set_thresholds(start, end)?;
+            if json {
+                let set = serde_json::json!({ "start": start, "end": end, "batteries": batteries });
+                println!("{set:#}");
+            } else {
+                println!(
+                    "Charge thresholds set to {start}% / {end}% on {}",
+                    batteries.join(", ")
+                );
+            }
+            Ok(())
+        }
+    }
 }
 
 fn run(
     terminal: &mut DefaultTerminal,
-    reader: BatteryReader,
-    battery_name: String,
+    mut source: Box<dyn BatterySource>,
+    config: Config,
+    cli: &Cli,
 ) -> io::Result<()> {
-    let mut app = App::new(battery_name);
+    let mut app = App::new(source.as_ref(), config);
+    app.update_thresholds(source.thresholds());
+    source.resume_after(app.history.samples.last().map(|s| s.timestamp));
 
     // Take initial sample
-    if let Some(sample) = reader.sample() {
-        app.add_sample(sample);
-    }
+    app.add_samples(source.sample());
+    app.update_health(source.health());
 
     let mut last_sample_time = Instant::now();
+    let mut last_health_time = Instant::now();
 
     loop {
         terminal.draw(|f| ui::render(f, &app))?;
@@ -70,20 +344,42 @@ fn run(
             match key.code {
                 // Quit
                 KeyCode::Char('q') => {
-                    app.history.save();
+                    app.save();
                     app.running = false;
                     return Ok(());
                 }
                 KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    app.history.save();
+                    app.save();
                     return Ok(());
                 }
 
                 // View switching
                 KeyCode::Char('d') => app.switch_to_dashboard(),
                 KeyCode::Char('h') => app.switch_to_history(),
-                KeyCode::Char('1') => app.switch_to_session(0),
-                KeyCode::Char('2') => app.switch_to_session(1),
+                KeyCode::Char('b') => app.cycle_battery(),
+                KeyCode::Char('w') => app.switch_to_health(),
+                KeyCode::Char('s') => app.switch_to_session_list(),
+                KeyCode::Esc if matches!(app.view, app::View::SessionDetail(..)) => {
+                    app.switch_to_session_list()
+                }
+
+                // Session list
+                KeyCode::Up | KeyCode::Char('k') if app.view == app::View::SessionList => {
+                    app.session_list_up()
+                }
+                KeyCode::Down | KeyCode::Char('j') if app.view == app::View::SessionList => {
+                    app.session_list_down()
+                }
+                KeyCode::Enter if app.view == app::View::SessionList => app.open_selected_session(),
+                KeyCode::Char('o') if app.view == app::View::SessionList => {
+                    app.cycle_session_sort()
+                }
+                KeyCode::Char('r') if app.view == app::View::SessionList => {
+                    app.reverse_session_sort()
+                }
+                KeyCode::Char('t') if app.view == app::View::SessionList => {
+                    app.toggle_session_kind()
+                }
 
                 // Zoom
                 KeyCode::Char('+') | KeyCode::Char('=') => {
@@ -104,7 +400,7 @@ fn run(
                 // Fit to data
                 KeyCode::Char('f') => match app.view {
                     app::View::HistoryChart => app.fit_viewport(),
-                    app::View::SessionDetail(idx) => app.fit_session_viewport(idx),
+                    app::View::SessionDetail(kind, start) => app.fit_session_viewport(kind, start),
                     _ => {}
                 },
 
@@ -113,41 +409,307 @@ fn run(
         }
 
         // Sample battery at interval
-        if last_sample_time.elapsed() >= SAMPLE_INTERVAL {
-            if let Some(sample) = reader.sample() {
-                app.add_sample(sample);
+        if last_sample_time.elapsed() >= app.sample_interval() {
+            app.sync_with_recorder();
+            app.update_thresholds(source.thresholds());
+            app.add_samples(source.sample());
+            app.add_session_events(source.session_events());
+            // The recorder went away: read the batteries directly from now on
+            if source.detached() {
+                source = Box::new(open_reader(cli)?);
             }
             last_sample_time = Instant::now();
         }
+
+        if last_health_time.elapsed() >= HEALTH_INTERVAL {
+            app.update_health(source.health());
+            last_health_time = Instant::now();
+        }
     }
 }
 
-fn run_headless(reader: BatteryReader) -> io::Result<()> {
-    let mut history = history::History::load();
-    let mut tick_count: u64 = 0;
+/// Record until SIGTERM or SIGINT, then save and return the exit status.
+/// SIGHUP reloads the config.
+fn run_headless(
+    source: &mut dyn BatterySource,
+    mut config: Config,
+    cli: &Cli,
+    args: &RecordArgs,
+) -> io::Result<i32> {
+    let lock_path = WriterLock::lock_path();
+    let Some(_lock) = WriterLock::try_acquire(&lock_path)? else {
+        let holder = WriterLock::holder(&lock_path)
+            .map(|pid| format!(" (pid {pid})"))
+            .unwrap_or_default();
+        eprintln!("bathis: another bathis process{holder} is already writing the history");
+        std::process::exit(1);
+    };
+    let signals = Signals::register()?;
+    let mut history = History::load();
+    apply_config(&mut history, &config);
+    history.resume_sessions(chrono::Local::now());
+    if let Some(warning) = &history.load_warning {
+        eprintln!("bathis: {warning}");
+    }
+    let mut health_log = HealthLog::load();
+    if let Some(warning) = &health_log.load_warning {
+        eprintln!("bathis: {warning}");
+    }
+
+    // TUIs attach through this socket instead of sampling on their own
+    let mut server = match RecorderServer::bind(&RecorderServer::socket_path()) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            eprintln!("bathis: cannot open the recorder socket ({e}); the TUI will not attach");
+            None
+        }
+    };
+    // Asked for explicitly, so not being able to listen is fatal
+    let exporter = match &args.metrics_listen {
+        Some(addr) => Some(MetricsServer::spawn(addr).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot serve metrics on {addr}: {e}"))
+        })?),
+        None => None,
+    };
+    let mut metrics = Metrics::new(&history);
+    let mut status = RecorderStatus {
+        pid: std::process::id(),
+        battery_name: source.battery_name(),
+        batteries: source.battery_ids(),
+        thresholds: source.thresholds(),
+        health: source.health(),
+    };
 
     eprintln!(
-        "bathis: recording battery samples every {}s (Ctrl+C to stop)",
-        SAMPLE_INTERVAL.as_secs()
+        "bathis: recording battery samples {} (Ctrl+C to stop)",
+        config.sampling.describe()
     );
+    if let Some(exporter) = &exporter {
+        eprintln!(
+            "bathis: serving metrics at http://{}/metrics",
+            exporter.local_addr()
+        );
+    }
 
     // Take initial sample
-    if let Some(sample) = reader.sample() {
-        history.add_sample(sample);
-        tick_count += 1;
+    history.charge_end_threshold = status.thresholds.as_ref().and_then(|t| t.end);
+    let mut errors = ErrorLog::default();
+    let samples = errors.sample(source.sample()).unwrap_or_default();
+    // The pack sample comes last and decides the adaptive interval
+    let mut interval = config.sampling.interval_after(samples.last());
+    record_samples(samples, &mut history, &mut metrics, args.json);
+    publish_metrics(
+        args,
+        exporter.as_ref(),
+        &metrics,
+        &history,
+        &status,
+        &mut errors,
+    );
+    let mut last_save = Instant::now();
+    if let Some(server) = &mut server {
+        server.track_sessions(&history);
     }
+    if health_log.record(&status.health) {
+        errors.health_save(health_log.save());
+    }
+    let mut last_health_time = Instant::now();
 
     loop {
-        thread::sleep(SAMPLE_INTERVAL);
+        let next_sample = Instant::now() + interval;
+        wait_until(next_sample, server.as_mut(), &history, &status, &signals);
+
+        if let Some(signal) = signals.stop_requested() {
+            history.close_sessions();
+            if let Err(e) = history.save() {
+                eprintln!("bathis: stopped, but the history was not saved: {e}");
+                return Ok(1);
+            }
+            eprintln!("bathis: stopped, history saved");
+            return Ok(Signals::exit_status(signal));
+        }
+        if signals.take_reload() {
+            match load_config(cli) {
+                Ok(reloaded) => {
+                    config = reloaded;
+                    apply_config(&mut history, &config);
+                    eprintln!(
+                        "bathis: reloaded the config, recording {}",
+                        config.sampling.describe()
+                    );
+                }
+                Err(e) => eprintln!("bathis: keeping the current config ({e})"),
+            }
+        }
+
+        status.thresholds = source.thresholds();
+        history.charge_end_threshold = status.thresholds.as_ref().and_then(|t| t.end);
+        if let Some(samples) = errors.sample(source.sample()) {
+            interval = config.sampling.interval_after(samples.last());
+            record_samples(samples, &mut history, &mut metrics, args.json);
+            publish_metrics(
+                args,
+                exporter.as_ref(),
+                &metrics,
+                &history,
+                &status,
+                &mut errors,
+            );
+
+            // Save finished sessions right away so attached TUIs can show them,
+            // everything else every `save_interval_secs`
+            let session_ended = server
+                .as_mut()
+                .is_some_and(|server| server.track_sessions(&history));
+            if session_ended || last_save.elapsed() >= config.sampling.save_interval() {
+                errors.history_save(history.save());
+                last_save = Instant::now();
+            }
+        }
+
+        if last_health_time.elapsed() >= HEALTH_INTERVAL {
+            status.health = source.health();
+            if health_log.record(&status.health) {
+                errors.health_save(health_log.save());
+            }
+            last_health_time = Instant::now();
+        }
+    }
+}
+
+/// Latest failure of each kind the recorder reports on stderr. A failure
+/// that keeps repeating is printed once, and recovery is printed too, so
+/// the journal shows when data went missing without filling up.
+#[derive(Default)]
+struct ErrorLog {
+    sample: Option<String>,
+    history_save: Option<String>,
+    health_save: Option<String>,
+    metrics_textfile: Option<String>,
+}
 
-        if let Some(sample) = reader.sample() {
-            history.add_sample(sample);
-            tick_count += 1;
+impl ErrorLog {
+    /// The samples of a successful reading
+    fn sample(&mut self, result: Result<Vec<BatterySample>, Error>) -> Option<Vec<BatterySample>> {
+        let (samples, error) = split(result);
+        report(&mut self.sample, error, "reading the batteries");
+        samples
+    }
+
+    fn history_save(&mut self, result: Result<(), Error>) {
+        report(
+            &mut self.history_save,
+            split(result).1,
+            "saving the history",
+        );
+    }
+
+    fn health_save(&mut self, result: Result<(), Error>) {
+        report(
+            &mut self.health_save,
+            split(result).1,
+            "saving the health log",
+        );
+    }
+
+    fn metrics_textfile(&mut self, result: Result<(), Error>) {
+        report(
+            &mut self.metrics_textfile,
+            split(result).1,
+            "writing the metrics file",
+        );
+    }
+}
+
+fn split<T>(result: Result<T, Error>) -> (Option<T>, Option<String>) {
+    match result {
+        Ok(value) => (Some(value), None),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+/// Print `error` unless it is the one printed last, or that `what` works
+/// again once it is gone
+fn report(last: &mut Option<String>, error: Option<String>, what: &str) {
+    match (&last, &error) {
+        (Some(_), None) => eprintln!("bathis: {what} works again"),
+        (_, Some(e)) if last.as_ref() != Some(e) => eprintln!("bathis: {e}"),
+        _ => {}
+    }
+    *last = error;
+}
 
-            // Auto-save every 60 ticks (~5 min at 5s interval)
-            if tick_count.is_multiple_of(60) {
-                history.save();
+/// Add one reading to the history and the metrics. With `record --json`,
+/// also print each pack sample as a line of JSON.
+fn record_samples(
+    samples: Vec<BatterySample>,
+    history: &mut History,
+    metrics: &mut Metrics,
+    json: bool,
+) {
+    if json {
+        for sample in samples.iter().filter(|s| s.battery.is_none()) {
+            if let Ok(line) = serde_json::to_string(sample) {
+                println!("{line}");
             }
         }
     }
+    metrics.add_samples(&samples);
+    samples.into_iter().for_each(|s| history.add_sample(s));
+    metrics.count_sessions(history);
+}
+
+/// Hand the latest metrics to the `--metrics-listen` endpoint and write
+/// the `--metrics-textfile`, for those that were asked for
+fn publish_metrics(
+    args: &RecordArgs,
+    exporter: Option<&MetricsServer>,
+    metrics: &Metrics,
+    history: &History,
+    status: &RecorderStatus,
+    errors: &mut ErrorLog,
+) {
+    if exporter.is_none() && args.metrics_textfile.is_none() {
+        return;
+    }
+    let text = metrics.render(history, status);
+    if let Some(path) = &args.metrics_textfile {
+        let result = metrics::write_textfile(path, &text).map_err(|source| Error::Textfile {
+            path: path.clone(),
+            source,
+        });
+        errors.metrics_textfile(result);
+    }
+    if let Some(exporter) = exporter {
+        exporter.publish(text);
+    }
+}
+
+/// Apply the session rules and sleep detection settings to the history
+fn apply_config(history: &mut History, config: &Config) {
+    history.rules = config.sessions.clone();
+    history.sleep_gap =
+        chrono::Duration::from_std(config.sampling.sleep_gap()).unwrap_or(history.sleep_gap);
+}
+
+/// Answer attached TUIs (if the socket is open) until `deadline`, or until
+/// a signal arrives
+fn wait_until(
+    deadline: Instant,
+    mut server: Option<&mut RecorderServer>,
+    history: &History,
+    status: &RecorderStatus,
+    signals: &Signals,
+) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline || signals.pending() {
+            return;
+        }
+        let step = (deadline - now).min(SIGNAL_CHECK_INTERVAL);
+        match server.as_deref_mut() {
+            Some(server) => server.serve_until(now + step, history, status),
+            None => thread::sleep(step),
+        }
+    }
 }
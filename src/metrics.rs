@@ -0,0 +1,387 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+use crate::battery::{BatterySample, BatteryStatus};
+use crate::history::History;
+use crate::recorder::RecorderStatus;
+
+/// Content type of the Prometheus text format, which OpenMetrics scrapers accept too
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// How long a scraper may take to send its request and read the answer
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Bytes of request line and headers read before giving up on a client
+const MAX_REQUEST_BYTES: u64 = 8192;
+
+const STATUSES: [(BatteryStatus, &str); 5] = [
+    (BatteryStatus::Charging, "charging"),
+    (BatteryStatus::Discharging, "discharging"),
+    (BatteryStatus::NotCharging, "not_charging"),
+    (BatteryStatus::Full, "full"),
+    (BatteryStatus::Unknown, "unknown"),
+];
+
+/// Name, help text and value of each per-battery gauge
+type Gauge = (
+    &'static str,
+    &'static str,
+    fn(&BatterySample) -> Option<f64>,
+);
+
+const GAUGES: [Gauge; 7] = [
+    ("capacity_percent", "Charge level", |s| Some(s.capacity)),
+    (
+        "power_watts",
+        "Power, positive while charging and negative while discharging",
+        |s| Some(s.power_watts),
+    ),
+    ("voltage_volts", "Voltage", |s| Some(s.voltage_now_v)),
+    ("energy_now_watt_hours", "Energy stored", |s| {
+        Some(s.energy_now_wh)
+    }),
+    ("energy_full_watt_hours", "Energy stored when full", |s| {
+        Some(s.energy_full_wh)
+    }),
+    ("temperature_celsius", "Temperature", |s| s.temperature_c),
+    (
+        "sample_timestamp_seconds",
+        "Time of the latest sample",
+        |s| Some(s.timestamp.timestamp_millis() as f64 / 1000.0),
+    ),
+];
+
+/// Counters the recorder keeps while it runs, from zero at start like any
+/// Prometheus counter
+#[derive(Debug, Default)]
+pub struct Metrics {
+    charge_sessions_completed: u64,
+    energy_charged_wh: f64,
+    /// Start of the newest charge session already counted
+    counted_until: Option<DateTime<Local>>,
+    last_pack: Option<BatterySample>,
+}
+
+impl Metrics {
+    /// Start counting after the sessions already in `history`
+    pub fn new(history: &History) -> Self {
+        Metrics {
+            counted_until: history.sessions().last().map(|s| s.start_time),
+            last_pack: history.samples.last().cloned(),
+            ..Metrics::default()
+        }
+    }
+
+    /// Count the energy charged since the previous pack sample
+    pub fn add_samples(&mut self, samples: &[BatterySample]) {
+        for sample in samples.iter().filter(|s| s.battery.is_none()) {
+            if let Some(last) = &self.last_pack
+                && last.status == BatteryStatus::Charging
+                && sample.status == BatteryStatus::Charging
+            {
+                self.energy_charged_wh += (energy_wh(sample) - energy_wh(last)).max(0.0);
+            }
+            self.last_pack = Some(sample.clone());
+        }
+    }
+
+    /// Count charge sessions finished since the last call
+    pub fn count_sessions(&mut self, history: &History) {
+        let counted_until = self.counted_until;
+        let new = history
+            .sessions()
+            .iter()
+            .filter(|s| counted_until.is_none_or(|t| s.start_time > t));
+        for session in new {
+            if session.completed {
+                self.charge_sessions_completed += 1;
+            }
+            self.counted_until = Some(session.start_time);
+        }
+    }
+
+    /// All metrics in the Prometheus text format
+    pub fn render(&self, history: &History, status: &RecorderStatus) -> String {
+        let mut latest: Vec<(&str, &BatterySample)> = history
+            .battery_samples
+            .iter()
+            .filter_map(|(name, series)| Some((name.as_str(), series.last()?)))
+            .collect();
+        latest.extend(history.samples.last().map(|s| ("pack", s)));
+
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP bathis_info Recorder details");
+        let _ = writeln!(out, "# TYPE bathis_info gauge");
+        let _ = writeln!(
+            out,
+            "bathis_info{{name=\"{}\",version=\"{}\"}} 1",
+            escape(&status.battery_name),
+            env!("CARGO_PKG_VERSION")
+        );
+
+        for (name, help, value) in GAUGES {
+            let values: Vec<(&str, f64)> = latest
+                .iter()
+                .filter_map(|&(battery, s)| Some((battery, value(s)?)))
+                .collect();
+            if values.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "# HELP bathis_battery_{name} {help}");
+            let _ = writeln!(out, "# TYPE bathis_battery_{name} gauge");
+            for (battery, value) in values {
+                let _ = writeln!(
+                    out,
+                    "bathis_battery_{name}{{battery=\"{}\"}} {value}",
+                    escape(battery)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP bathis_battery_status Charging status, 1 for the current one"
+        );
+        let _ = writeln!(out, "# TYPE bathis_battery_status gauge");
+        for (battery, sample) in &latest {
+            for (status, label) in STATUSES {
+                let _ = writeln!(
+                    out,
+                    "bathis_battery_status{{battery=\"{}\",status=\"{label}\"}} {}",
+                    escape(battery),
+                    u8::from(sample.status == status)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP bathis_charge_sessions_completed_total Charge sessions that reached their target since the recorder started"
+        );
+        let _ = writeln!(out, "# TYPE bathis_charge_sessions_completed_total counter");
+        let _ = writeln!(
+            out,
+            "bathis_charge_sessions_completed_total {}",
+            self.charge_sessions_completed
+        );
+        let _ = writeln!(
+            out,
+            "# HELP bathis_energy_charged_watt_hours_total Energy charged into the pack since the recorder started"
+        );
+        let _ = writeln!(out, "# TYPE bathis_energy_charged_watt_hours_total counter");
+        let _ = writeln!(
+            out,
+            "bathis_energy_charged_watt_hours_total {}",
+            self.energy_charged_wh
+        );
+        out
+    }
+}
+
+/// Stored energy, from capacity × full energy for batteries that report none
+fn energy_wh(sample: &BatterySample) -> f64 {
+    if sample.energy_now_wh > 0.0 {
+        sample.energy_now_wh
+    } else {
+        sample.capacity / 100.0 * sample.energy_full_wh
+    }
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write the metrics for node_exporter's textfile collector. The file is
+/// replaced in one step, so the collector never reads half of it.
+pub fn write_textfile(path: &Path, metrics: &str) -> io::Result<()> {
+    let tmp = path.with_extension("prom.tmp");
+    fs::write(&tmp, metrics)?;
+    fs::rename(&tmp, path)
+}
+
+/// HTTP endpoint serving the metrics at `/metrics` from a thread of its
+/// own, so a slow scraper never holds up sampling. It answers with the
+/// metrics last passed to `publish`.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    metrics: Arc<Mutex<String>>,
+}
+
+impl MetricsServer {
+    pub fn spawn(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let server = MetricsServer {
+            addr: listener.local_addr()?,
+            metrics: Arc::default(),
+        };
+        let metrics = Arc::clone(&server.metrics);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A scraper that hangs up early is its own problem
+                let _ = answer(stream, || {
+                    metrics.lock().map(|m| m.clone()).unwrap_or_default()
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Serve `metrics` from now on
+    pub fn publish(&self, metrics: String) {
+        if let Ok(mut current) = self.metrics.lock() {
+            *current = metrics;
+        }
+    }
+}
+
+/// Reads from a stream until one deadline for the whole request, however
+/// slowly the client sends
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Read one HTTP request and answer it, within `SCRAPE_TIMEOUT` overall
+fn answer(mut stream: TcpStream, render: impl FnOnce() -> String) -> io::Result<()> {
+    let deadline = Instant::now() + SCRAPE_TIMEOUT;
+    let mut reader = BufReader::new(
+        DeadlineReader {
+            stream: &stream,
+            deadline,
+        }
+        .take(MAX_REQUEST_BYTES),
+    );
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Headers do not matter, but the client expects them to be read
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path.split('?').next() == Some("/metrics") => {
+            ("200 OK", CONTENT_TYPE, render())
+        }
+        (Some("GET"), Some("/")) => (
+            "200 OK",
+            "text/plain; charset=utf-8",
+            "bathis recorder: metrics are at /metrics\n".to_string(),
+        ),
+        (Some("GET"), _) => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            "not found\n".to_string(),
+        ),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain; charset=utf-8",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+    let left = deadline.saturating_duration_since(Instant::now());
+    stream.set_write_timeout(Some(left.max(Duration::from_millis(1))))?;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn sample(minute: i64, capacity: f64, status: BatteryStatus) -> BatterySample {
+        test_support::sample(minute * 60, capacity, 30.0, status)
+    }
+
+    #[test]
+    fn counters_start_at_zero_and_follow_charging() {
+        let mut history = History::new();
+        let mut metrics = Metrics::new(&history);
+        // 80% → 92% at 0.5 Wh per percent, then unplugged
+        let samples: Vec<BatterySample> = (0..=12)
+            .map(|i| sample(i, 80.0 + i as f64, BatteryStatus::Charging))
+            .chain([sample(13, 92.0, BatteryStatus::Discharging)])
+            .collect();
+        metrics.add_samples(&samples);
+        samples.into_iter().for_each(|s| history.add_sample(s));
+        metrics.count_sessions(&history);
+        metrics.count_sessions(&history);
+
+        let text = metrics.render(&history, &RecorderStatus::default());
+        assert!(text.contains("bathis_charge_sessions_completed_total 1\n"));
+        assert!(text.contains("bathis_energy_charged_watt_hours_total 6\n"));
+        assert!(text.contains("bathis_battery_capacity_percent{battery=\"pack\"} 92\n"));
+        assert!(
+            text.contains("bathis_battery_status{battery=\"pack\",status=\"discharging\"} 1\n")
+        );
+        assert!(text.contains("bathis_battery_status{battery=\"pack\",status=\"charging\"} 0\n"));
+        assert!(!text.contains("temperature"));
+
+        // A restarted recorder counts from zero again
+        let restarted = Metrics::new(&history);
+        let text = restarted.render(&history, &RecorderStatus::default());
+        assert!(text.contains("bathis_charge_sessions_completed_total 0\n"));
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let server = MetricsServer::spawn("127.0.0.1:0").unwrap();
+        let mut history = History::new();
+        history.add_sample(sample(0, 50.0, BatteryStatus::Discharging));
+        let metrics = Metrics::new(&history);
+        server.publish(metrics.render(&history, &RecorderStatus::default()));
+
+        // A client that never sends its request is dropped after SCRAPE_TIMEOUT
+        let _silent = TcpStream::connect(server.local_addr()).unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        for (path, expected) in [
+            ("/metrics", "HTTP/1.1 200 OK"),
+            ("/other", "HTTP/1.1 404 Not Found"),
+        ] {
+            let response = get(path);
+            assert!(response.starts_with(expected), "{response}");
+            if path == "/metrics" {
+                assert!(response.contains("bathis_battery_voltage_volts{battery=\"pack\"} 12\n"));
+            }
+        }
+    }
+}
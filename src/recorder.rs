@@ -0,0 +1,656 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::battery::{BatterySample, ChargeThresholds};
+use crate::error::Error;
+use crate::health::BatteryHealth;
+use crate::history::{History, SessionKind};
+use crate::source::BatterySource;
+
+/// How often the recorder looks for client requests between samples
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long a client waits for the recorder to answer, and how long the
+/// recorder waits for a client to take a reply before dropping it
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest request line; a client sending more without a newline is dropped
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+/// Session events kept for clients that poll late
+const MAX_EVENTS: usize = 100;
+
+/// A request from a client, sent as one line of JSON
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    /// Recorder and battery details
+    Status,
+    /// Latest sample of every battery and the pack
+    Current,
+    /// Samples of one series (the pack when `battery` is `None`) newer than
+    /// `after` and no newer than `until`
+    Samples {
+        #[serde(default)]
+        battery: Option<String>,
+        #[serde(default)]
+        after: Option<DateTime<Local>>,
+        #[serde(default)]
+        until: Option<DateTime<Local>>,
+    },
+    /// Session events with a sequence number above `after`
+    Events { after: u64 },
+}
+
+/// The recorder's answer to a [`Request`], sent as one line of JSON
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    Status(RecorderStatus),
+    Samples { samples: Vec<BatterySample> },
+    Events { events: Vec<SessionEvent> },
+    Error { message: String },
+}
+
+/// What the recorder knows about the batteries it samples
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecorderStatus {
+    pub pid: u32,
+    pub battery_name: String,
+    /// Individual batteries (empty when there is only one)
+    pub batteries: Vec<String>,
+    pub thresholds: Option<ChargeThresholds>,
+    pub health: Vec<BatteryHealth>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionChange {
+    Started,
+    Ended,
+}
+
+/// A charge or discharge session starting or ending
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionEvent {
+    /// Increases by one with every event
+    pub seq: u64,
+    pub kind: SessionKind,
+    pub change: SessionChange,
+    pub time: DateTime<Local>,
+    /// Pack capacity when the session started or ended
+    pub capacity: f64,
+}
+
+// --- Server ---
+
+/// A connected client, the part of its next request read so far and the
+/// replies it has not taken yet
+struct Connection {
+    stream: UnixStream,
+    buf: Vec<u8>,
+    out: Vec<u8>,
+    /// When the client last took part of `out`, or `out` was filled
+    last_progress: Instant,
+}
+
+/// Unix socket through which `bathis record` serves its samples and
+/// session events, so a TUI can attach instead of sampling on its own
+pub struct RecorderServer {
+    listener: UnixListener,
+    path: PathBuf,
+    clients: Vec<Connection>,
+    events: Vec<SessionEvent>,
+    next_seq: u64,
+    /// Start of the active charge and discharge session, as last seen
+    active_charge: Option<DateTime<Local>>,
+    active_discharge: Option<DateTime<Local>>,
+}
+
+impl RecorderServer {
+    /// Listen on `path`, replacing a socket left behind by an earlier recorder.
+    /// Only the holder of the writer lock should do this.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(RecorderServer {
+            listener,
+            path: path.to_path_buf(),
+            clients: Vec::new(),
+            events: Vec::new(),
+            next_seq: 1,
+            active_charge: None,
+            active_discharge: None,
+        })
+    }
+
+    pub fn socket_path() -> PathBuf {
+        match dirs::runtime_dir() {
+            Some(dir) => dir.join("bathis.sock"),
+            None => dirs::data_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("bathis")
+                .join("recorder.sock"),
+        }
+    }
+
+    /// Answer client requests until `deadline`
+    pub fn serve_until(&mut self, deadline: Instant, history: &History, status: &RecorderStatus) {
+        loop {
+            self.poll(history, status);
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Accept new clients and answer every complete request, without blocking
+    pub fn poll(&mut self, history: &History, status: &RecorderStatus) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.clients.push(Connection {
+                    stream,
+                    buf: Vec::new(),
+                    out: Vec::new(),
+                    last_progress: Instant::now(),
+                });
+            }
+        }
+
+        let mut clients = std::mem::take(&mut self.clients);
+        clients.retain_mut(|client| self.serve_client(client, history, status).is_ok());
+        self.clients = clients;
+    }
+
+    /// Read what `client` has sent and queue an answer to each complete
+    /// line, then send what the socket takes without blocking. An error
+    /// (including the client hanging up, sending an overlong line or not
+    /// taking its replies) drops the connection.
+    fn serve_client(
+        &self,
+        client: &mut Connection,
+        history: &History,
+        status: &RecorderStatus,
+    ) -> io::Result<()> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match client.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    client.buf.extend_from_slice(&chunk[..n]);
+                    if client.buf.len() > MAX_REQUEST_BYTES && !client.buf.contains(&b'\n') {
+                        return Err(io::ErrorKind::InvalidData.into());
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        while let Some(end) = client.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = client.buf.drain(..=end).collect();
+            let response = match serde_json::from_slice::<Request>(&line) {
+                Ok(request) => self.answer(request, history, status),
+                Err(e) => Response::Error {
+                    message: format!("bad request: {e}"),
+                },
+            };
+            if client.out.is_empty() {
+                client.last_progress = Instant::now();
+            }
+            serde_json::to_writer(&mut client.out, &response).map_err(io::Error::other)?;
+            client.out.push(b'\n');
+        }
+
+        // Large replies may not fit the socket buffer in one go; the rest
+        // goes out on later polls
+        while !client.out.is_empty() {
+            match client.stream.write(&client.out) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    client.out.drain(..n);
+                    client.last_progress = Instant::now();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        if !client.out.is_empty() && client.last_progress.elapsed() > CLIENT_TIMEOUT {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        Ok(())
+    }
+
+    fn answer(&self, request: Request, history: &History, status: &RecorderStatus) -> Response {
+        match request {
+            Request::Status => Response::Status(status.clone()),
+            Request::Current => {
+                let mut samples: Vec<BatterySample> = history
+                    .battery_samples
+                    .values()
+                    .filter_map(|series| series.last().cloned())
+                    .collect();
+                samples.extend(history.samples.last().cloned());
+                Response::Samples { samples }
+            }
+            Request::Samples {
+                battery,
+                after,
+                until,
+            } => {
+                let series = history.samples_for(battery.as_deref());
+                // Series are in time order, so the range is one slice
+                let start = after.map_or(0, |t| series.partition_point(|s| s.timestamp <= t));
+                let end = until.map_or(series.len(), |t| {
+                    series.partition_point(|s| s.timestamp <= t)
+                });
+                Response::Samples {
+                    samples: series[start..end.max(start)].to_vec(),
+                }
+            }
+            Request::Events { after } => Response::Events {
+                events: self
+                    .events
+                    .iter()
+                    .filter(|e| e.seq > after)
+                    .cloned()
+                    .collect(),
+            },
+        }
+    }
+
+    /// Turn changes of the active sessions since the last call into events.
+    /// Returns true when a session ended.
+    pub fn track_sessions(&mut self, history: &History) -> bool {
+        let charge = history
+            .active_session
+            .as_ref()
+            .map(|s| (s.start_time, s.start_capacity));
+        let discharge = history
+            .active_discharge
+            .as_ref()
+            .map(|s| (s.start_time, s.start_capacity));
+
+        let mut ended = false;
+        for (kind, now) in [
+            (SessionKind::Charge, charge),
+            (SessionKind::Discharge, discharge),
+        ] {
+            let before = match kind {
+                SessionKind::Charge => self.active_charge,
+                SessionKind::Discharge => self.active_discharge,
+            };
+            if before == now.map(|(start, _)| start) {
+                continue;
+            }
+            if let Some(start) = before {
+                ended |= self.push_end(history, kind, start);
+            }
+            if let Some((start, capacity)) = now {
+                self.push(kind, SessionChange::Started, start, capacity);
+            }
+            match kind {
+                SessionKind::Charge => self.active_charge = now.map(|(start, _)| start),
+                SessionKind::Discharge => self.active_discharge = now.map(|(start, _)| start),
+            }
+        }
+        ended
+    }
+
+    /// Record the end of the session of `kind` that started at `start`.
+    /// Sessions too short to be kept end without an event.
+    fn push_end(&mut self, history: &History, kind: SessionKind, start: DateTime<Local>) -> bool {
+        let session = (0..history.session_count(kind))
+            .rev()
+            .filter_map(|idx| history.session(kind, idx))
+            .find(|s| s.start_time() == start);
+        let Some(session) = session else {
+            return false;
+        };
+        let time = session.end_time().unwrap_or(start);
+        let capacity = session.samples().last().map_or(0.0, |s| s.capacity);
+        self.push(kind, SessionChange::Ended, time, capacity);
+        true
+    }
+
+    fn push(
+        &mut self,
+        kind: SessionKind,
+        change: SessionChange,
+        time: DateTime<Local>,
+        capacity: f64,
+    ) {
+        self.events.push(SessionEvent {
+            seq: self.next_seq,
+            kind,
+            change,
+            time,
+            capacity,
+        });
+        self.next_seq += 1;
+        if self.events.len() > MAX_EVENTS {
+            self.events.remove(0);
+        }
+    }
+}
+
+impl Drop for RecorderServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// --- Client ---
+
+/// Battery source that takes its samples from a running recorder instead of
+/// reading sysfs. Once the recorder goes away it reports itself detached.
+pub struct RecorderClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    status: RecorderStatus,
+    /// Newest pack sample received so far
+    after: Option<DateTime<Local>>,
+    /// Sequence number of the last session event received
+    events_after: u64,
+    events: Vec<SessionEvent>,
+    detached: bool,
+}
+
+impl RecorderClient {
+    /// Connect to the recorder listening on `path`
+    pub fn connect(path: &Path) -> io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut client = RecorderClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            status: RecorderStatus::default(),
+            after: None,
+            events_after: 0,
+            events: Vec::new(),
+            detached: false,
+        };
+        client.status = client.status()?;
+        // Only events from now on are news
+        client.events_after = client.events()?.last().map_or(0, |e| e.seq);
+        Ok(client)
+    }
+
+    fn request(&mut self, request: &Request) -> io::Result<Response> {
+        let mut line = serde_json::to_vec(request).map_err(io::Error::other)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+
+        let mut reply = String::new();
+        if self.reader.read_line(&mut reply)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        match serde_json::from_str(&reply) {
+            Ok(Response::Error { message }) => Err(io::Error::other(message)),
+            Ok(response) => Ok(response),
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    fn status(&mut self) -> io::Result<RecorderStatus> {
+        match self.request(&Request::Status)? {
+            Response::Status(status) => Ok(status),
+            _ => Err(unexpected()),
+        }
+    }
+
+    fn samples(&mut self, request: &Request) -> io::Result<Vec<BatterySample>> {
+        match self.request(request)? {
+            Response::Samples { samples } => Ok(samples),
+            _ => Err(unexpected()),
+        }
+    }
+
+    fn events(&mut self) -> io::Result<Vec<SessionEvent>> {
+        match self.request(&Request::Events {
+            after: self.events_after,
+        })? {
+            Response::Events { events } => Ok(events),
+            _ => Err(unexpected()),
+        }
+    }
+
+    /// Samples recorded since the last call: every battery's first, then the pack
+    fn fetch(&mut self) -> io::Result<Vec<BatterySample>> {
+        self.status = self.status()?;
+
+        let samples = match self.after {
+            None => self.samples(&Request::Current)?,
+            Some(after) => {
+                let mut samples = Vec::new();
+                for battery in self.status.batteries.clone() {
+                    samples.extend(self.samples(&Request::Samples {
+                        battery: Some(battery),
+                        after: Some(after),
+                        until: None,
+                    })?);
+                }
+                samples.extend(self.samples(&Request::Samples {
+                    battery: None,
+                    after: Some(after),
+                    until: None,
+                })?);
+                // Keep each tick's battery samples ahead of its pack sample
+                samples.sort_by_key(|s| s.timestamp);
+                samples
+            }
+        };
+        if let Some(newest) = samples.iter().rev().find(|s| s.battery.is_none()) {
+            self.after = Some(newest.timestamp);
+        }
+
+        let events = self.events()?;
+        if let Some(last) = events.last() {
+            self.events_after = last.seq;
+        }
+        self.events.extend(events);
+        Ok(samples)
+    }
+}
+
+fn unexpected() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "unexpected response from the recorder",
+    )
+}
+
+impl BatterySource for RecorderClient {
+    fn battery_name(&self) -> String {
+        self.status.battery_name.clone()
+    }
+
+    fn battery_ids(&self) -> Vec<String> {
+        self.status.batteries.clone()
+    }
+
+    /// Never fails: losing the recorder detaches the client instead
+    fn sample(&mut self) -> Result<Vec<BatterySample>, Error> {
+        if self.detached {
+            return Ok(Vec::new());
+        }
+        Ok(self.fetch().unwrap_or_else(|_| {
+            self.detached = true;
+            Vec::new()
+        }))
+    }
+
+    fn health(&self) -> Vec<BatteryHealth> {
+        self.status.health.clone()
+    }
+
+    fn thresholds(&self) -> Option<ChargeThresholds> {
+        self.status.thresholds.clone()
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+
+    fn resume_after(&mut self, after: Option<DateTime<Local>>) {
+        self.after = after;
+    }
+
+    fn session_events(&mut self) -> Vec<SessionEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn detached(&self) -> bool {
+        self.detached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::BatteryStatus;
+    use crate::test_support::{self, temp_dir};
+
+    fn sample(minute: i64, capacity: f64, status: BatteryStatus) -> BatterySample {
+        test_support::sample(minute * 60, capacity, 0.0, status)
+    }
+
+    /// Answer requests until `client` has done its work
+    fn serve<T: Send + 'static>(
+        server: &mut RecorderServer,
+        history: &History,
+        status: &RecorderStatus,
+        client: impl FnOnce() -> T + Send + 'static,
+    ) -> T {
+        let handle = thread::spawn(client);
+        while !handle.is_finished() {
+            server.poll(history, status);
+            thread::sleep(Duration::from_millis(5));
+        }
+        handle.join().unwrap()
+    }
+
+    #[test]
+    fn client_follows_recorder_samples_and_sessions() {
+        let dir = temp_dir("recorder");
+        let path = dir.join("recorder.sock");
+        let mut server = RecorderServer::bind(&path).unwrap();
+        let status = RecorderStatus {
+            pid: 4242,
+            battery_name: "BAT0".to_string(),
+            ..RecorderStatus::default()
+        };
+
+        let mut history = History::new();
+        for m in 0..3 {
+            history.add_sample(sample(m, 50.0 + m as f64, BatteryStatus::Charging));
+        }
+        server.track_sessions(&history);
+
+        let client_path = path.clone();
+        let mut client = serve(&mut server, &history, &status, move || {
+            let mut client = RecorderClient::connect(&client_path).unwrap();
+            // The TUI has minute 0 already; it gets the rest
+            client.resume_after(Some(sample(0, 0.0, BatteryStatus::Unknown).timestamp));
+            let samples = client.sample().unwrap();
+            (client, samples)
+        });
+        assert_eq!(client.0.status.pid, 4242);
+        assert_eq!(client.0.battery_name(), "BAT0");
+        let capacities: Vec<f64> = client.1.iter().map(|s| s.capacity).collect();
+        assert_eq!(capacities, vec![51.0, 52.0]);
+        // Events from before connecting are not news
+        assert!(client.0.session_events().is_empty());
+
+        // Unplug: the charge session ends and a discharge session starts
+        history.add_sample(sample(3, 52.0, BatteryStatus::Discharging));
+        assert!(server.track_sessions(&history));
+        let (mut client, samples) = serve(&mut server, &history, &status, move || {
+            let mut client = client.0;
+            let samples = client.sample().unwrap();
+            (client, samples)
+        });
+        assert_eq!(samples.len(), 1);
+        let events = client.session_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            (events[0].kind, events[0].change, events[0].capacity),
+            (SessionKind::Charge, SessionChange::Ended, 52.0)
+        );
+        assert_eq!(
+            (events[1].kind, events[1].change),
+            (SessionKind::Discharge, SessionChange::Started)
+        );
+
+        // The recorder stops: the client notices and detaches
+        drop(server);
+        assert!(client.sample().unwrap().is_empty());
+        assert!(client.detached());
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn large_replies_go_out_in_pieces_and_overlong_requests_are_dropped() {
+        let dir = temp_dir("recorder-big");
+        let path = dir.join("recorder.sock");
+        let mut server = RecorderServer::bind(&path).unwrap();
+        let mut history = History::new();
+        // Two days of minutes, far more than a socket buffer holds
+        for m in 0..2880 {
+            history.add_sample(sample(m, 50.0, BatteryStatus::Discharging));
+        }
+
+        let client_path = path.clone();
+        let reply = serve(
+            &mut server,
+            &history,
+            &RecorderStatus::default(),
+            move || {
+                let mut stream = UnixStream::connect(&client_path).unwrap();
+                stream.write_all(b"{\"request\":\"samples\"}\n").unwrap();
+                let mut line = String::new();
+                BufReader::new(stream).read_line(&mut line).unwrap();
+                line
+            },
+        );
+        match serde_json::from_str(&reply).unwrap() {
+            Response::Samples { samples } => assert_eq!(samples.len(), history.samples.len()),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let client_path = path.clone();
+        let read = serve(
+            &mut server,
+            &history,
+            &RecorderStatus::default(),
+            move || {
+                let mut stream = UnixStream::connect(&client_path).unwrap();
+                stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+                // The recorder may hang up before all of it is written
+                let _ = stream.write_all(&vec![b'x'; MAX_REQUEST_BYTES + 1]);
+                stream.read(&mut [0u8; 16])
+            },
+        );
+        assert!(matches!(read, Ok(0)) || read.is_err(), "{read:?}");
+        assert!(server.clients.is_empty());
+        drop(server);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
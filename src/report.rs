@@ -0,0 +1,585 @@
+use std::fmt::Write as _;
+use std::io;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::battery::{BatterySample, ChargeThresholds, describe_adapters};
+use crate::estimate::{self, ChargeCurve, Remaining};
+use crate::format::{format_duration, format_remaining};
+use crate::health::{BatteryHealth, HealthLog};
+use crate::history::{History, SessionKind, SessionStats, SleepSummary};
+use crate::rollup::{Resolution, Rollup};
+use crate::source::BatterySource;
+
+/// How far back the status estimate looks for samples to smooth the power over
+pub const RECENT_SAMPLES: chrono::Duration = chrono::Duration::hours(1);
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+fn json(value: &impl Serialize) -> io::Result<String> {
+    let mut out = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    out.push('\n');
+    Ok(out)
+}
+
+/// One reading, as `bathis status` prints it
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub battery_name: String,
+    #[serde(flatten)]
+    pub pack: BatterySample,
+    /// The individual batteries (empty when there is only one)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub batteries: Vec<BatterySample>,
+    #[serde(skip)]
+    pub remaining: Option<Remaining>,
+    pub time_to_empty_secs: Option<f64>,
+    pub time_to_full_secs: Option<f64>,
+    pub thresholds: Option<ChargeThresholds>,
+    pub health: Vec<BatteryHealth>,
+}
+
+impl Status {
+    /// Build the status from one reading of `source`. The time left is
+    /// smoothed over the last hour of `earlier` pack samples, if any.
+    pub fn new(
+        source: &dyn BatterySource,
+        reading: Vec<BatterySample>,
+        earlier: &[BatterySample],
+        curve: &ChargeCurve,
+    ) -> Option<Self> {
+        let (batteries, pack): (Vec<_>, Vec<_>) =
+            reading.into_iter().partition(|s| s.battery.is_some());
+        let pack = pack.into_iter().last()?;
+
+        let mut recent: Vec<BatterySample> = earlier
+            .iter()
+            .filter(|s| {
+                s.timestamp < pack.timestamp && pack.timestamp - s.timestamp < RECENT_SAMPLES
+            })
+            .cloned()
+            .collect();
+        recent.push(pack.clone());
+        let thresholds = source.thresholds();
+        let target = estimate::charge_target(thresholds.as_ref());
+        let remaining = estimate::remaining(&recent, target, curve);
+
+        Some(Status {
+            battery_name: source.battery_name(),
+            pack,
+            batteries,
+            remaining,
+            time_to_empty_secs: match remaining {
+                Some(Remaining::Empty(secs)) => Some(secs),
+                _ => None,
+            },
+            time_to_full_secs: match remaining {
+                Some(Remaining::Full { secs, .. }) => Some(secs),
+                _ => None,
+            },
+            thresholds,
+            health: source.health(),
+        })
+    }
+
+    pub fn render(&self, as_json: bool) -> io::Result<String> {
+        if as_json {
+            return json(self);
+        }
+        let pack = &self.pack;
+        let mut out = format!(
+            "{}: {:.1}% {}\n",
+            self.battery_name, pack.capacity, pack.status
+        );
+        let _ = writeln!(out, "  Power:    {:+.2} W", pack.power_watts);
+        if let Some(remaining) = self.remaining {
+            let _ = writeln!(out, "  Left:     {}", format_remaining(remaining));
+        }
+        let _ = writeln!(
+            out,
+            "  Energy:   {:.2} / {:.2} Wh",
+            pack.energy_now_wh, pack.energy_full_wh
+        );
+        let _ = writeln!(out, "  Voltage:  {:.3} V", pack.voltage_now_v);
+        if let Some(temp) = pack.temperature_c {
+            let _ = writeln!(out, "  Temp:     {temp:.1} °C");
+        }
+        let supply = match describe_adapters(&pack.adapters) {
+            Some(adapter) => format!("on AC ({adapter})"),
+            None => "on battery".to_string(),
+        };
+        let _ = writeln!(out, "  Supply:   {supply}");
+        if let Some(thresholds) = &self.thresholds {
+            let _ = writeln!(out, "  Limits:   {thresholds}");
+        }
+        for health in &self.health {
+            let _ = write!(out, "  Health:   {}", health.battery);
+            if let Some(percent) = health.health_percent() {
+                let _ = write!(out, " {percent:.1}% of design");
+            }
+            if let Some(cycles) = health.cycle_count {
+                let _ = write!(out, ", {cycles} cycles");
+            }
+            out.push('\n');
+        }
+        for battery in &self.batteries {
+            let _ = writeln!(
+                out,
+                "  {}: {:.1}% {} {:+.2} W",
+                battery.battery.as_deref().unwrap_or_default(),
+                battery.capacity,
+                battery.status,
+                battery.power_watts
+            );
+        }
+        Ok(out)
+    }
+}
+
+/// One finished session, as `bathis sessions` lists it
+#[derive(Debug, Serialize)]
+struct SessionLine {
+    kind: SessionKind,
+    start_time: DateTime<Local>,
+    end_time: Option<DateTime<Local>>,
+    start_capacity: f64,
+    end_capacity: f64,
+    duration_secs: f64,
+    energy_wh: f64,
+    average_watts: f64,
+    peak_watts: f64,
+    /// Whether a charge reached its target (charge sessions only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    charger: Option<String>,
+}
+
+impl SessionLine {
+    fn new(
+        kind: SessionKind,
+        session: &dyn SessionStats,
+        (start_capacity, end_capacity): (f64, f64),
+    ) -> Self {
+        SessionLine {
+            kind,
+            start_time: session.start_time(),
+            end_time: session.end_time(),
+            start_capacity,
+            end_capacity,
+            duration_secs: session.duration_secs(),
+            energy_wh: session.energy_wh(),
+            average_watts: session.average_watts(),
+            peak_watts: session.peak_watts(),
+            completed: None,
+            charger: None,
+        }
+    }
+}
+
+/// Finished sessions of `kind`, oldest first; `limit` keeps the newest ones
+pub fn sessions(
+    history: &History,
+    kind: SessionKind,
+    limit: Option<usize>,
+    as_json: bool,
+) -> io::Result<String> {
+    let lines: Vec<SessionLine> = match kind {
+        SessionKind::Charge => history
+            .charge_sessions
+            .iter()
+            .map(|s| SessionLine {
+                completed: Some(s.completed),
+                charger: s.charger.clone(),
+                ..SessionLine::new(kind, s, (s.start_capacity, s.end_capacity))
+            })
+            .collect(),
+        SessionKind::Discharge => history
+            .discharge_sessions
+            .iter()
+            .map(|s| SessionLine::new(kind, s, (s.start_capacity, s.end_capacity)))
+            .collect(),
+    };
+    let skip = limit.map_or(0, |n| lines.len().saturating_sub(n));
+    let lines = &lines[skip..];
+    if as_json {
+        return json(&lines);
+    }
+    if lines.is_empty() {
+        return Ok(format!("No {} sessions yet\n", kind.label().to_lowercase()));
+    }
+
+    let mut out = format!(
+        "{:<16}  {:>8}  {:>11}  {:>9}  {:>7}  {:>7}",
+        "Start", "Duration", "Capacity", "Energy", "Avg W", "Peak W"
+    );
+    if kind == SessionKind::Charge {
+        out.push_str("  Result     Charger");
+    }
+    out.push('\n');
+    for line in lines {
+        let _ = write!(
+            out,
+            "{:<16}  {:>8}  {:>11}  {:>6.1} Wh  {:>7.1}  {:>7.1}",
+            line.start_time.format(TIME_FORMAT),
+            format_duration(line.duration_secs),
+            format!("{:.0}% → {:.0}%", line.start_capacity, line.end_capacity),
+            line.energy_wh,
+            line.average_watts,
+            line.peak_watts
+        );
+        if let Some(completed) = line.completed {
+            let result = if completed { "completed" } else { "partial" };
+            let _ = write!(
+                out,
+                "  {result:<9}  {}",
+                line.charger.as_deref().unwrap_or("-")
+            );
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// One rollup bucket of one series, as `bathis export` writes it
+#[derive(Serialize)]
+struct RollupLine<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    battery: Option<&'a str>,
+    #[serde(flatten)]
+    rollup: &'a Rollup,
+}
+
+/// The saved history at `resolution`, every series or just `battery`
+/// ("pack" for the combined pack). Raw samples come out as CSV that
+/// `--replay` reads back.
+pub fn export(
+    history: &History,
+    resolution: Resolution,
+    battery: Option<&str>,
+    as_json: bool,
+) -> io::Result<String> {
+    // Per-battery series first, so each tick's battery samples stay ahead
+    // of its pack sample once sorted
+    let names = history.battery_names();
+    let series: Vec<Option<&str>> = match battery {
+        Some("pack") => vec![None],
+        Some(name) if names.iter().any(|n| n == name) => vec![Some(name)],
+        Some(name) => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no battery named {name} in the history"),
+            ));
+        }
+        None => names
+            .iter()
+            .map(|n| Some(n.as_str()))
+            .chain([None])
+            .collect(),
+    };
+
+    if resolution == Resolution::Raw {
+        let mut samples: Vec<&BatterySample> = series
+            .iter()
+            .flat_map(|&battery| history.samples_for(battery))
+            .collect();
+        samples.sort_by_key(|s| s.timestamp);
+        if as_json {
+            return json(&samples);
+        }
+        let mut out = String::from(
+            "timestamp,battery,capacity,power_watts,status,energy_now_wh,energy_full_wh,voltage_now_v,temperature_c\n",
+        );
+        for s in samples {
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{}",
+                s.timestamp.to_rfc3339(),
+                s.battery.as_deref().unwrap_or_default(),
+                s.capacity,
+                s.power_watts,
+                s.status,
+                s.energy_now_wh,
+                s.energy_full_wh,
+                s.voltage_now_v,
+                s.temperature_c.map(|t| t.to_string()).unwrap_or_default()
+            );
+        }
+        return Ok(out);
+    }
+
+    let mut rollups: Vec<RollupLine> = series
+        .iter()
+        .flat_map(|&battery| {
+            history
+                .rollups_for(resolution, battery)
+                .iter()
+                .map(move |rollup| RollupLine { battery, rollup })
+        })
+        .collect();
+    rollups.sort_by_key(|r| r.rollup.start);
+    if as_json {
+        return json(&rollups);
+    }
+    let mut out = String::from(
+        "start,battery,count,seconds,capacity_min,capacity_avg,capacity_max,power_min,power_avg,power_max\n",
+    );
+    for RollupLine { battery, rollup: r } in rollups {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{}",
+            r.start.to_rfc3339(),
+            battery.unwrap_or_default(),
+            r.count,
+            r.seconds,
+            r.capacity.min,
+            r.capacity.avg,
+            r.capacity.max,
+            r.power_watts.min,
+            r.power_watts.avg,
+            r.power_watts.max
+        );
+    }
+    Ok(out)
+}
+
+/// Totals and averages over the finished sessions of one kind
+#[derive(Debug, Default, Serialize)]
+pub struct SessionTotals {
+    pub count: usize,
+    /// Charge sessions that reached their target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<usize>,
+    pub energy_wh: f64,
+    pub hours: f64,
+    pub average_duration_secs: f64,
+    pub average_energy_wh: f64,
+    /// Energy over time across all sessions, so long sessions count for more
+    pub average_watts: f64,
+}
+
+impl SessionTotals {
+    fn new<'a>(sessions: impl Iterator<Item = &'a dyn SessionStats>) -> Self {
+        let mut totals = SessionTotals::default();
+        for session in sessions {
+            totals.count += 1;
+            totals.energy_wh += session.energy_wh();
+            totals.hours += session.duration_secs() / 3600.0;
+        }
+        if totals.count > 0 {
+            totals.average_duration_secs = totals.hours * 3600.0 / totals.count as f64;
+            totals.average_energy_wh = totals.energy_wh / totals.count as f64;
+        }
+        if totals.hours > 0.0 {
+            totals.average_watts = totals.energy_wh / totals.hours;
+        }
+        totals
+    }
+}
+
+/// Summary of the saved history, as `bathis stats` prints it
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub first_sample: Option<DateTime<Local>>,
+    pub last_sample: Option<DateTime<Local>>,
+    pub charge: SessionTotals,
+    pub discharge: SessionTotals,
+    /// Time a full battery lasts at the average discharge drain
+    pub full_runtime_secs: Option<f64>,
+    pub sleep: Option<SleepSummary>,
+    /// Latest health snapshot of each battery
+    pub health: Vec<BatteryHealth>,
+}
+
+impl Stats {
+    pub fn new(history: &History, health_log: &HealthLog) -> Self {
+        let mut charge = SessionTotals::new(
+            history
+                .charge_sessions
+                .iter()
+                .map(|s| s as &dyn SessionStats),
+        );
+        charge.completed = Some(
+            history
+                .charge_sessions
+                .iter()
+                .filter(|s| s.completed)
+                .count(),
+        );
+        let discharge = SessionTotals::new(
+            history
+                .discharge_sessions
+                .iter()
+                .map(|s| s as &dyn SessionStats),
+        );
+        let energy_full = history.samples.last().map_or(0.0, |s| s.energy_full_wh);
+        let full_runtime_secs = (discharge.average_watts > 0.0 && energy_full > 0.0)
+            .then(|| energy_full / discharge.average_watts * 3600.0);
+
+        Stats {
+            first_sample: history.first_timestamp(None),
+            last_sample: history.samples.last().map(|s| s.timestamp),
+            charge,
+            discharge,
+            full_runtime_secs,
+            sleep: history.sleep_summary(),
+            health: health_log
+                .batteries()
+                .iter()
+                .filter_map(|name| health_log.series(name).last().map(|s| s.health.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn render(&self, as_json: bool) -> io::Result<String> {
+        if as_json {
+            return json(self);
+        }
+        let mut out = String::new();
+        match (self.first_sample, self.last_sample) {
+            (Some(first), Some(last)) => {
+                let _ = writeln!(
+                    out,
+                    "History:      {} → {}",
+                    first.format(TIME_FORMAT),
+                    last.format(TIME_FORMAT)
+                );
+            }
+            _ => out.push_str("History:      empty\n"),
+        }
+
+        let charge = &self.charge;
+        let _ = writeln!(
+            out,
+            "Charging:     {} sessions, {} completed, {:.1} Wh charged",
+            charge.count,
+            charge.completed.unwrap_or(0),
+            charge.energy_wh
+        );
+        if charge.count > 0 {
+            let _ = writeln!(
+                out,
+                "              average {}, {:.1} Wh at {:.1} W",
+                format_duration(charge.average_duration_secs),
+                charge.average_energy_wh,
+                charge.average_watts
+            );
+        }
+
+        let discharge = &self.discharge;
+        let _ = writeln!(
+            out,
+            "Discharging:  {} sessions, {:.1} Wh used",
+            discharge.count, discharge.energy_wh
+        );
+        if discharge.count > 0 {
+            let _ = writeln!(
+                out,
+                "              average {}, {:.1} Wh at {:.1} W",
+                format_duration(discharge.average_duration_secs),
+                discharge.average_energy_wh,
+                discharge.average_watts
+            );
+        }
+        if let Some(secs) = self.full_runtime_secs {
+            let _ = writeln!(
+                out,
+                "              ~{} on a full battery at that drain",
+                format_duration(secs)
+            );
+        }
+
+        if let Some(sleep) = self.sleep {
+            let _ = writeln!(
+                out,
+                "Sleep:        -{:.2} %/h over {} ({} sleeps)",
+                sleep.drain_per_hour,
+                format_duration(sleep.hours * 3600.0),
+                sleep.count
+            );
+        }
+        for health in &self.health {
+            let _ = write!(out, "Health:       {}", health.battery);
+            if let Some(percent) = health.health_percent() {
+                let _ = write!(out, " {percent:.1}% of design");
+            }
+            if let Some(cycles) = health.cycle_count {
+                let _ = write!(out, ", {cycles} cycles");
+            }
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::BatteryStatus;
+    use crate::history::ChargeSession;
+    use crate::source::parse_csv;
+    use crate::test_support;
+
+    fn sample(secs: i64, battery: Option<&str>, capacity: f64) -> BatterySample {
+        BatterySample {
+            battery: battery.map(str::to_string),
+            temperature_c: Some(30.5),
+            ..test_support::sample(secs, capacity, 20.0, BatteryStatus::NotCharging)
+        }
+    }
+
+    #[test]
+    fn exported_csv_replays_the_same_samples() {
+        let mut history = History::new();
+        for i in 0..3 {
+            history.add_sample(sample(i * 5, Some("BAT0"), 50.0 + i as f64));
+            history.add_sample(sample(i * 5, None, 50.0 + i as f64));
+        }
+
+        let csv = export(&history, Resolution::Raw, None, false).unwrap();
+        let samples = parse_csv(&csv).unwrap();
+        assert_eq!(samples.len(), 6);
+        assert_eq!(samples[0].battery.as_deref(), Some("BAT0"));
+        assert_eq!(samples[1].battery, None);
+        assert_eq!(samples[5].capacity, 52.0);
+        assert_eq!(samples[5].status, BatteryStatus::NotCharging);
+        assert_eq!(samples[5].temperature_c, Some(30.5));
+
+        let pack = export(&history, Resolution::Raw, Some("pack"), false).unwrap();
+        assert_eq!(parse_csv(&pack).unwrap().len(), 3);
+        assert!(export(&history, Resolution::Raw, Some("BAT9"), false).is_err());
+    }
+
+    #[test]
+    fn session_averages_weight_by_duration() {
+        let session = |secs: i64, capacity: f64| {
+            let samples = vec![sample(0, None, 20.0), sample(secs, None, capacity)];
+            ChargeSession {
+                start_time: samples[0].timestamp,
+                end_time: Some(samples[1].timestamp),
+                start_capacity: 20.0,
+                end_capacity: capacity,
+                samples,
+                completed: capacity >= 90.0,
+                charger: None,
+            }
+        };
+        let mut history = History::new();
+        // 10 Wh in 1 h and 35 Wh in 2 h: 45 Wh in 3 h
+        history.charge_sessions = vec![session(3600, 40.0), session(7200, 90.0)];
+
+        let stats = Stats::new(&history, &HealthLog::new());
+        assert_eq!(stats.charge.count, 2);
+        assert_eq!(stats.charge.completed, Some(1));
+        assert!((stats.charge.energy_wh - 45.0).abs() < 1e-9);
+        assert!((stats.charge.average_watts - 15.0).abs() < 1e-9);
+        assert_eq!(stats.charge.average_duration_secs, 5400.0);
+
+        let listed = sessions(&history, SessionKind::Charge, Some(1), true).unwrap();
+        let listed: serde_json::Value = serde_json::from_str(&listed).unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["end_capacity"], 90.0);
+        assert_eq!(listed[0]["completed"], true);
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::{DateTime, Duration, Local, TimeZone};
+use serde::Serialize;
+
+use crate::battery::BatterySample;
+
+/// How finely a stretch of history is kept. Raw samples cover the last two
+/// days; older data only survives as per-minute and per-quarter-hour rollups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resolution {
+    Raw,
+    Minute,
+    QuarterHour,
+}
+
+impl Resolution {
+    /// The rollup tiers, finest first
+    pub const ROLLUPS: [Resolution; 2] = [Resolution::Minute, Resolution::QuarterHour];
+
+    /// Time covered by one point (the default sampling interval for raw samples)
+    pub fn step(self) -> Duration {
+        match self {
+            Resolution::Raw => Duration::seconds(5),
+            Resolution::Minute => Duration::minutes(1),
+            Resolution::QuarterHour => Duration::minutes(15),
+        }
+    }
+
+    /// How far back from the newest point this tier is kept
+    pub fn retention(self) -> Duration {
+        match self {
+            Resolution::Raw => Duration::hours(48),
+            Resolution::Minute => Duration::days(30),
+            Resolution::QuarterHour => Duration::days(365),
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Resolution::Raw => "raw samples",
+            Resolution::Minute => "1 min rollups",
+            Resolution::QuarterHour => "15 min rollups",
+        }
+    }
+
+    /// Name used in the database
+    pub fn name(self) -> &'static str {
+        match self {
+            Resolution::Raw => "raw",
+            Resolution::Minute => "minute",
+            Resolution::QuarterHour => "quarter_hour",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Resolution::Raw),
+            "minute" => Some(Resolution::Minute),
+            "quarter_hour" => Some(Resolution::QuarterHour),
+            _ => None,
+        }
+    }
+
+    /// Start of the bucket `time` falls into
+    fn bucket_start(self, time: DateTime<Local>) -> DateTime<Local> {
+        let step = self.step().num_milliseconds();
+        let ms = time.timestamp_millis().div_euclid(step) * step;
+        Local.timestamp_millis_opt(ms).single().unwrap_or(time)
+    }
+}
+
+/// Minimum, maximum and mean of one value over a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Stat {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+impl Stat {
+    fn new(value: f64) -> Self {
+        Stat {
+            min: value,
+            max: value,
+            avg: value,
+        }
+    }
+
+    /// Fold in one more value standing for `weight` seconds, on top of the
+    /// `total` seconds already in
+    fn add(&mut self, value: f64, weight: f64, total: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if total + weight > 0.0 {
+            self.avg += (value - self.avg) * weight / (total + weight);
+        }
+    }
+}
+
+/// Capacity and power over one bucket of a rollup tier
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rollup {
+    pub start: DateTime<Local>,
+    /// Raw samples folded into this bucket
+    pub count: u32,
+    /// Time those samples stand for; averages are weighted by it, so
+    /// stretches sampled more often do not count for more
+    pub seconds: f64,
+    pub capacity: Stat,
+    pub power_watts: Stat,
+}
+
+impl Rollup {
+    fn new(start: DateTime<Local>, sample: &BatterySample, weight: f64) -> Self {
+        Rollup {
+            start,
+            count: 1,
+            seconds: weight,
+            capacity: Stat::new(sample.capacity),
+            power_watts: Stat::new(sample.power_watts),
+        }
+    }
+
+    fn add(&mut self, sample: &BatterySample, weight: f64) {
+        self.capacity.add(sample.capacity, weight, self.seconds);
+        self.power_watts
+            .add(sample.power_watts, weight, self.seconds);
+        self.count += 1;
+        self.seconds += weight;
+    }
+}
+
+/// Fold `sample` into the last bucket of `series`, or start a new one, then
+/// drop buckets past the tier's retention. `previous` is the series' sample
+/// before this one: a sample stands for the time since then, at least a
+/// second and at most one bucket. Returns true if a bucket was started.
+pub fn add_sample(
+    series: &mut Vec<Rollup>,
+    resolution: Resolution,
+    sample: &BatterySample,
+    previous: Option<DateTime<Local>>,
+) -> bool {
+    let step = resolution.step().num_milliseconds() as f64 / 1000.0;
+    let weight = previous
+        .map(|t| (sample.timestamp - t).num_milliseconds() as f64 / 1000.0)
+        .unwrap_or(0.0)
+        .clamp(1.0, step);
+
+    let start = resolution.bucket_start(sample.timestamp);
+    let started = match series.last_mut() {
+        // Late samples count towards the newest bucket
+        Some(last) if start <= last.start => {
+            last.add(sample, weight);
+            false
+        }
+        _ => {
+            series.push(Rollup::new(start, sample, weight));
+            true
+        }
+    };
+    if let Some(newest) = series.last().map(|r| r.start) {
+        let cutoff = newest - resolution.retention();
+        let expired = series.partition_point(|r| r.start < cutoff);
+        series.drain(..expired);
+    }
+    started
+}
+
+/// Roll up a whole series of raw samples
+pub fn build(resolution: Resolution, samples: &[BatterySample]) -> Vec<Rollup> {
+    let mut series = Vec::new();
+    let mut previous = None;
+    for sample in samples {
+        add_sample(&mut series, resolution, sample, previous);
+        previous = Some(sample.timestamp);
+    }
+    series
+}
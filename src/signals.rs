@@ -0,0 +1,59 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::flag;
+
+/// Stop and reload requests the recorder gets as signals. SIGTERM and
+/// SIGINT ask it to stop after saving; a second one ends it right away.
+/// SIGHUP asks it to reload the config.
+pub struct Signals {
+    /// Number of the signal that asked to stop (0 = none yet)
+    stop: Arc<AtomicUsize>,
+    stopping: Arc<AtomicBool>,
+    reload: Arc<AtomicBool>,
+}
+
+impl Signals {
+    pub fn register() -> io::Result<Self> {
+        let signals = Signals {
+            stop: Arc::new(AtomicUsize::new(0)),
+            stopping: Arc::new(AtomicBool::new(false)),
+            reload: Arc::new(AtomicBool::new(false)),
+        };
+        for signal in [SIGTERM, SIGINT] {
+            // Runs before the flag is set, so it only fires on the second signal
+            flag::register_conditional_shutdown(signal, 1, Arc::clone(&signals.stopping))?;
+            flag::register(signal, Arc::clone(&signals.stopping))?;
+            flag::register_usize(signal, Arc::clone(&signals.stop), signal as usize)?;
+        }
+        flag::register(SIGHUP, Arc::clone(&signals.reload))?;
+        Ok(signals)
+    }
+
+    /// The signal that asked to stop, once one has arrived
+    pub fn stop_requested(&self) -> Option<i32> {
+        match self.stop.load(Ordering::Relaxed) {
+            0 => None,
+            signal => Some(signal as i32),
+        }
+    }
+
+    /// True once for every SIGHUP
+    pub fn take_reload(&self) -> bool {
+        self.reload.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether a signal is waiting to be handled
+    pub fn pending(&self) -> bool {
+        self.stop_requested().is_some() || self.reload.load(Ordering::Relaxed)
+    }
+
+    /// Exit status after stopping cleanly on `signal`: success for SIGTERM,
+    /// which is how service managers stop the recorder, and the shell's
+    /// 128 + signal for Ctrl+C
+    pub fn exit_status(signal: i32) -> i32 {
+        if signal == SIGTERM { 0 } else { 128 + signal }
+    }
+}
@@ -0,0 +1,453 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+use chrono::{DateTime, Duration, Local};
+
+use crate::battery::{
+    AdapterState, BatteryReader, BatterySample, BatteryStatus, ChargeThresholds, EnergySource,
+};
+use crate::error::Error;
+use crate::health::BatteryHealth;
+use crate::history::History;
+use crate::recorder::SessionEvent;
+use crate::store::Store;
+
+/// Anything that can produce battery samples: real sysfs, a recording, or a simulation
+pub trait BatterySource {
+    /// Display name for the whole pack
+    fn battery_name(&self) -> String;
+
+    /// Names of the individual batteries (empty when there is only one)
+    fn battery_ids(&self) -> Vec<String>;
+
+    /// Take the samples that are due now.
+    ///
+    /// Uses the same tagging as [`BatteryReader::sample`]: per-battery samples carry
+    /// `battery: Some(..)`, the pack sample has `battery: None`.
+    fn sample(&mut self) -> Result<Vec<BatterySample>, Error>;
+
+    /// Cycle count, design capacity and wear of each battery, if known
+    fn health(&self) -> Vec<BatteryHealth> {
+        Vec::new()
+    }
+
+    /// Firmware charge thresholds, if the battery supports them
+    fn thresholds(&self) -> Option<ChargeThresholds> {
+        None
+    }
+
+    /// Whether samples from this source belong in the user's history file
+    fn is_live(&self) -> bool {
+        false
+    }
+
+    /// Have the next `sample` return everything newer than `after`, for
+    /// sources that keep a history of their own (the recorder)
+    fn resume_after(&mut self, _after: Option<DateTime<Local>>) {}
+
+    /// Sessions started or ended since the last call, for sources that track
+    /// sessions themselves
+    fn session_events(&mut self) -> Vec<SessionEvent> {
+        Vec::new()
+    }
+
+    /// True once the source has lost the process its samples come from
+    fn detached(&self) -> bool {
+        false
+    }
+}
+
+impl BatterySource for BatteryReader {
+    fn battery_name(&self) -> String {
+        BatteryReader::battery_name(self)
+    }
+
+    fn battery_ids(&self) -> Vec<String> {
+        match BatteryReader::battery_ids(self) {
+            ids if ids.len() > 1 => ids,
+            _ => Vec::new(),
+        }
+    }
+
+    fn sample(&mut self) -> Result<Vec<BatterySample>, Error> {
+        BatteryReader::sample(self)
+    }
+
+    fn health(&self) -> Vec<BatteryHealth> {
+        BatteryReader::health(self)
+    }
+
+    fn thresholds(&self) -> Option<ChargeThresholds> {
+        BatteryReader::thresholds(self)
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+}
+
+/// Simulated time that runs `speed` times faster than the wall clock
+struct SimClock {
+    started: Instant,
+    start_time: DateTime<Local>,
+    speed: f64,
+}
+
+impl SimClock {
+    fn new(start_time: DateTime<Local>, speed: f64) -> Self {
+        SimClock {
+            started: Instant::now(),
+            start_time,
+            speed: speed.max(0.01),
+        }
+    }
+
+    fn now(&self) -> DateTime<Local> {
+        let elapsed = self.started.elapsed().as_secs_f64() * self.speed;
+        self.start_time + Duration::milliseconds((elapsed * 1000.0) as i64)
+    }
+}
+
+// --- Replay ---
+
+/// Plays back a recorded history database, history.json or CSV file, keeping the
+/// original timestamps
+pub struct ReplaySource {
+    name: String,
+    batteries: Vec<String>,
+    samples: Vec<BatterySample>,
+    next: usize,
+    clock: SimClock,
+}
+
+impl ReplaySource {
+    /// Load a recording. Files ending in `.csv` are parsed as CSV, `.db` files
+    /// are read as a bathis history database, anything else as a history.json.
+    pub fn open(path: &Path, speed: f64) -> io::Result<Self> {
+        let has_extension = |want: &str| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(want))
+        };
+
+        let mut samples = if has_extension("db") {
+            read_store(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else if has_extension("csv") {
+            parse_csv(&fs::read_to_string(path)?)?
+        } else {
+            let data = fs::read_to_string(path)?;
+            let history: History = serde_json::from_str(&data).map_err(|source| Error::Json {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            let mut samples = history.samples;
+            samples.extend(history.battery_samples.into_values().flatten());
+            samples
+        };
+
+        if samples.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} contains no samples", path.display()),
+            ));
+        }
+        // Stable sort keeps per-battery samples ahead of the pack sample of the same tick
+        samples.sort_by_key(|s| s.timestamp);
+
+        let mut batteries: Vec<String> = samples.iter().filter_map(|s| s.battery.clone()).collect();
+        batteries.sort();
+        batteries.dedup();
+
+        let name = path
+            .file_name()
+            .map(|s| format!("replay of {}", s.to_string_lossy()))
+            .unwrap_or_else(|| "replay".to_string());
+        let clock = SimClock::new(samples[0].timestamp, speed);
+
+        Ok(ReplaySource {
+            name,
+            batteries,
+            samples,
+            next: 0,
+            clock,
+        })
+    }
+}
+
+impl BatterySource for ReplaySource {
+    fn battery_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn battery_ids(&self) -> Vec<String> {
+        self.batteries.clone()
+    }
+
+    fn sample(&mut self) -> Result<Vec<BatterySample>, Error> {
+        let now = self.clock.now();
+        let due = self.samples[self.next..]
+            .iter()
+            .take_while(|s| s.timestamp <= now)
+            .count();
+        let out = self.samples[self.next..self.next + due].to_vec();
+        self.next += due;
+        Ok(out)
+    }
+}
+
+/// All samples in a bathis history database
+fn read_store(path: &Path) -> rusqlite::Result<Vec<BatterySample>> {
+    if !path.exists() {
+        return Err(rusqlite::Error::InvalidPath(path.to_path_buf()));
+    }
+    // A recording is input only, so it is never switched to WAL
+    let store = Store::open_read_only(path)?;
+    let mut samples = store.load_samples(None, usize::MAX)?;
+    for name in store.battery_names()? {
+        samples.extend(store.load_samples(Some(&name), usize::MAX)?);
+    }
+    Ok(samples)
+}
+
+/// Parse CSV with a header row such as
+/// `timestamp,battery,capacity,power_watts,status,energy_now_wh,energy_full_wh,voltage_now_v`.
+/// Columns are matched by name, so extra or reordered columns are fine;
+/// `timestamp` (RFC 3339) and `capacity` are required; `temperature_c` is optional.
+pub fn parse_csv(data: &str) -> io::Result<Vec<BatterySample>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut lines = data.lines().filter(|l| !l.trim().is_empty());
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or_else(|| invalid("empty CSV file".to_string()))?
+        .split(',')
+        .map(str::trim)
+        .collect();
+    let column = |name: &str| header.iter().position(|h| *h == name);
+    let ts_col = column("timestamp").ok_or_else(|| invalid("missing timestamp column".into()))?;
+    let cap_col = column("capacity").ok_or_else(|| invalid("missing capacity column".into()))?;
+
+    let mut samples = Vec::new();
+    for (line_no, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let field = |col: Option<usize>| col.and_then(|c| fields.get(c).copied());
+        let number = |name: &str| {
+            field(column(name))
+                .and_then(|v| v.parse::<f64>().ok())
+                .unwrap_or(0.0)
+        };
+
+        let timestamp = field(Some(ts_col))
+            .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+            .ok_or_else(|| invalid(format!("line {}: bad timestamp", line_no + 2)))?
+            .with_timezone(&Local);
+        let capacity = field(Some(cap_col))
+            .and_then(|v| v.parse::<f64>().ok())
+            .ok_or_else(|| invalid(format!("line {}: bad capacity", line_no + 2)))?;
+
+        samples.push(BatterySample {
+            timestamp,
+            battery: field(column("battery"))
+                .filter(|v| !v.is_empty())
+                .map(str::to_string),
+            capacity,
+            power_watts: number("power_watts"),
+            status: field(column("status"))
+                .map(BatteryStatus::parse)
+                .unwrap_or(BatteryStatus::Unknown),
+            energy_now_wh: number("energy_now_wh"),
+            energy_full_wh: number("energy_full_wh"),
+            voltage_now_v: number("voltage_now_v"),
+            source: EnergySource::Energy,
+            temperature_c: field(column("temperature_c")).and_then(|v| v.parse::<f64>().ok()),
+            adapters: Vec::new(),
+        });
+    }
+    Ok(samples)
+}
+
+// --- Synthetic ---
+
+/// Simulated interval between synthetic samples
+const SYNTHETIC_STEP_SECS: i64 = 5;
+
+/// Simulates a laptop cycling between discharge and a CC/CV charge curve
+pub struct SyntheticSource {
+    clock: SimClock,
+    /// Simulated time of the last emitted sample
+    sim_time: DateTime<Local>,
+    energy_full_wh: f64,
+    energy_now_wh: f64,
+    status: BatteryStatus,
+    /// Seconds spent in the current status
+    phase_secs: f64,
+    temperature_c: f64,
+}
+
+impl SyntheticSource {
+    /// Discharge floor where the simulated user plugs in
+    const PLUG_IN_AT: f64 = 20.0;
+    /// Charger rating during the constant-current phase
+    const CHARGE_WATTS: f64 = 45.0;
+    /// Capacity where the constant-voltage taper starts
+    const CV_START: f64 = 80.0;
+
+    pub fn new(speed: f64) -> Self {
+        let now = Local::now();
+        SyntheticSource {
+            clock: SimClock::new(now, speed),
+            // One step in the past so the first sample is due immediately
+            sim_time: now - Duration::seconds(SYNTHETIC_STEP_SECS),
+            energy_full_wh: 50.0,
+            energy_now_wh: 35.0,
+            status: BatteryStatus::Discharging,
+            phase_secs: 0.0,
+            temperature_c: 28.0,
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        self.energy_now_wh / self.energy_full_wh * 100.0
+    }
+
+    /// Signed power for the current state at simulated time `t`
+    fn power_at(&self, t: f64) -> f64 {
+        match self.status {
+            BatteryStatus::Charging => {
+                let capacity = self.capacity();
+                if capacity < Self::CV_START {
+                    Self::CHARGE_WATTS
+                } else {
+                    // Taper linearly towards a trickle at 100%
+                    let left = (100.0 - capacity) / (100.0 - Self::CV_START);
+                    (Self::CHARGE_WATTS * left).max(1.5)
+                }
+            }
+            BatteryStatus::Discharging => {
+                // Idle baseline with periodic bursts of load
+                let load = 4.0 * (t / 900.0).sin().max(0.0) + 1.5 * (t / 47.0).sin().abs();
+                -(6.0 + load)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Advance the simulation by one step and return the new sample
+    fn step(&mut self) -> BatterySample {
+        let dt = SYNTHETIC_STEP_SECS as f64;
+        self.sim_time += Duration::seconds(SYNTHETIC_STEP_SECS);
+        self.phase_secs += dt;
+
+        let t = self.phase_secs;
+        let power = self.power_at(t);
+        self.energy_now_wh =
+            (self.energy_now_wh + power * dt / 3600.0).clamp(0.0, self.energy_full_wh);
+
+        let next = match self.status {
+            BatteryStatus::Discharging if self.capacity() <= Self::PLUG_IN_AT => {
+                Some(BatteryStatus::Charging)
+            }
+            BatteryStatus::Charging if self.capacity() >= 100.0 => Some(BatteryStatus::Full),
+            // Unplug after sitting full for half an hour
+            BatteryStatus::Full if t >= 1800.0 => Some(BatteryStatus::Discharging),
+            _ => None,
+        };
+        if let Some(status) = next {
+            self.status = status;
+            self.phase_secs = 0.0;
+        }
+
+        // Heat up towards a target set by power flow, with a ~10 minute time constant
+        let target_c = 26.0 + power.abs() * 0.35;
+        self.temperature_c += (target_c - self.temperature_c) * (dt / 600.0);
+
+        // Voltage follows state of charge, a little higher while charging
+        let capacity = self.capacity();
+        let voltage = 10.8
+            + 2.0 * capacity / 100.0
+            + if self.status == BatteryStatus::Charging {
+                0.3
+            } else {
+                0.0
+            };
+
+        BatterySample {
+            timestamp: self.sim_time,
+            battery: None,
+            capacity: capacity.round(),
+            power_watts: self.power_at(self.phase_secs),
+            status: self.status,
+            energy_now_wh: self.energy_now_wh,
+            energy_full_wh: self.energy_full_wh,
+            voltage_now_v: voltage,
+            source: EnergySource::Energy,
+            temperature_c: Some(self.temperature_c),
+            adapters: match self.status {
+                BatteryStatus::Discharging => Vec::new(),
+                _ => vec![AdapterState {
+                    name: "USBC000:001".to_string(),
+                    kind: "USB".to_string(),
+                    usb_type: Some("PD".to_string()),
+                    voltage_max_v: Some(20.0),
+                    current_max_a: Some(2.25),
+                }],
+            },
+        }
+    }
+}
+
+impl BatterySource for SyntheticSource {
+    fn battery_name(&self) -> String {
+        "Synthetic battery".to_string()
+    }
+
+    fn battery_ids(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn sample(&mut self) -> Result<Vec<BatterySample>, Error> {
+        let now = self.clock.now();
+        let mut out = Vec::new();
+        while self.sim_time + Duration::seconds(SYNTHETIC_STEP_SECS) <= now {
+            out.push(self.step());
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_columns_are_matched_by_name() {
+        let csv = "capacity,timestamp,status,power_watts\n\
+                   55,2026-01-05T10:00:00+01:00,Discharging,-7.5\n\
+                   54,2026-01-05T10:00:05+01:00,Not charging,0\n";
+        let samples = parse_csv(csv).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].capacity, 55.0);
+        assert_eq!(samples[0].power_watts, -7.5);
+        assert_eq!(samples[0].battery, None);
+        assert_eq!(samples[1].status, BatteryStatus::NotCharging);
+
+        assert!(parse_csv("capacity\n55\n").is_err());
+    }
+
+    #[test]
+    fn synthetic_cycle_produces_a_completed_charge_session() {
+        let mut source = SyntheticSource::new(1.0);
+        let mut history = History::new();
+        // A full discharge + charge cycle is well under two simulated days
+        for _ in 0..(2 * 24 * 3600 / SYNTHETIC_STEP_SECS) {
+            history.add_sample(source.step());
+            if !history.sessions().is_empty() {
+                break;
+            }
+        }
+        let session = &history.sessions()[0];
+        assert!(session.start_capacity <= SyntheticSource::PLUG_IN_AT);
+        assert!(session.end_capacity >= 90.0);
+    }
+}
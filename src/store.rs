@@ -0,0 +1,859 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use rusqlite::{Connection, ErrorCode, OpenFlags, Row, Transaction, params, params_from_iter};
+
+use crate::battery::{BatterySample, BatteryStatus, EnergySource};
+use crate::health::{BatteryHealth, HealthSnapshot};
+use crate::history::{SessionKind, SleepPeriod};
+use crate::rollup::{Resolution, Rollup, Stat};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS samples (
+        timestamp_ms INTEGER NOT NULL,
+        battery TEXT,
+        capacity REAL NOT NULL,
+        power_watts REAL NOT NULL,
+        status TEXT NOT NULL,
+        energy_now_wh REAL NOT NULL,
+        energy_full_wh REAL NOT NULL,
+        voltage_now_v REAL NOT NULL,
+        source TEXT NOT NULL,
+        temperature_c REAL,
+        adapters TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS samples_by_time ON samples (battery, timestamp_ms);
+
+    CREATE TABLE IF NOT EXISTS sessions (
+        id INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        start_ms INTEGER NOT NULL,
+        end_ms INTEGER,
+        start_capacity REAL NOT NULL,
+        end_capacity REAL NOT NULL,
+        completed INTEGER NOT NULL,
+        charger TEXT,
+        active INTEGER NOT NULL,
+        UNIQUE (kind, start_ms)
+    );
+
+    CREATE TABLE IF NOT EXISTS session_samples (
+        session_id INTEGER NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        timestamp_ms INTEGER NOT NULL,
+        battery TEXT,
+        capacity REAL NOT NULL,
+        power_watts REAL NOT NULL,
+        status TEXT NOT NULL,
+        energy_now_wh REAL NOT NULL,
+        energy_full_wh REAL NOT NULL,
+        voltage_now_v REAL NOT NULL,
+        source TEXT NOT NULL,
+        temperature_c REAL,
+        adapters TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS session_samples_by_session ON session_samples (session_id);
+
+    CREATE TABLE IF NOT EXISTS rollups (
+        resolution TEXT NOT NULL,
+        battery TEXT,
+        start_ms INTEGER NOT NULL,
+        count INTEGER NOT NULL,
+        seconds REAL NOT NULL,
+        capacity_min REAL NOT NULL,
+        capacity_max REAL NOT NULL,
+        capacity_avg REAL NOT NULL,
+        power_min REAL NOT NULL,
+        power_max REAL NOT NULL,
+        power_avg REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS rollups_by_time ON rollups (resolution, battery, start_ms);
+
+    CREATE TABLE IF NOT EXISTS sleeps (
+        start_ms INTEGER PRIMARY KEY,
+        end_ms INTEGER NOT NULL,
+        start_capacity REAL NOT NULL,
+        end_capacity REAL NOT NULL,
+        on_battery INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS health_snapshots (
+        date TEXT NOT NULL,
+        battery TEXT NOT NULL,
+        cycle_count INTEGER,
+        energy_full_wh REAL NOT NULL,
+        energy_full_design_wh REAL NOT NULL,
+        technology TEXT,
+        serial_number TEXT,
+        PRIMARY KEY (date, battery)
+    );
+";
+
+/// Rotating backups kept next to the database; `history.db.1` is the newest
+const BACKUP_COUNT: u32 = 3;
+
+/// Minimum age of the newest backup before another one is taken
+const BACKUP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Sample columns shared by `samples` and `session_samples`
+const SAMPLE_COLUMNS: &str = "timestamp_ms, battery, capacity, power_watts, status, \
+     energy_now_wh, energy_full_wh, voltage_now_v, source, temperature_c, adapters";
+
+/// Session fields as stored, without the samples
+#[derive(Debug, Clone)]
+pub struct SessionRow {
+    pub start_time: DateTime<Local>,
+    pub end_time: Option<DateTime<Local>>,
+    pub start_capacity: f64,
+    pub end_capacity: f64,
+    /// Always false for discharge sessions
+    pub completed: bool,
+    pub charger: Option<String>,
+    /// Still in progress when it was written
+    pub active: bool,
+}
+
+/// A session to insert or update, with its samples. Samples already stored
+/// for the session are skipped, so an in-progress session only appends.
+pub struct SessionWrite<'a> {
+    pub kind: SessionKind,
+    pub row: SessionRow,
+    pub samples: &'a [BatterySample],
+}
+
+/// Everything that changed since the last write, applied in one transaction
+#[derive(Default)]
+pub struct WriteBatch<'a> {
+    /// New samples, tagged or not
+    pub samples: Vec<&'a BatterySample>,
+    /// Sessions closed since the last write and the ones in progress
+    pub sessions: Vec<SessionWrite<'a>>,
+    /// Per series (None = pack), drop stored samples older than this
+    pub keep_samples_from: Vec<(Option<&'a str>, DateTime<Local>)>,
+    /// New or updated rollup buckets, replacing the stored bucket
+    pub rollups: Vec<(Resolution, Option<&'a str>, &'a Rollup)>,
+    /// Per tier and series, drop stored buckets older than this
+    pub keep_rollups_from: Vec<(Resolution, Option<&'a str>, DateTime<Local>)>,
+    /// Sleeps detected since the last write
+    pub sleeps: Vec<&'a SleepPeriod>,
+    /// Drop stored sleeps that started before this
+    pub keep_sleeps_from: Option<DateTime<Local>>,
+    /// Per kind, drop finished sessions that started before this
+    /// (None = drop all finished sessions of that kind)
+    pub keep_sessions_from: Vec<(SessionKind, Option<DateTime<Local>>)>,
+}
+
+/// SQLite database holding samples, sessions and health snapshots
+#[derive(Debug)]
+pub struct Store {
+    conn: Connection,
+    path: PathBuf,
+}
+
+impl Store {
+    /// Open (or create) the database at `path` and check that it is intact
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let check: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+        if check != "ok" {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+                Some(check),
+            ));
+        }
+        conn.execute_batch(SCHEMA)?;
+        Ok(Store {
+            conn,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Open the database at `path` for reading only. Nothing is created or
+    /// checked, which keeps it cheap for commands run often.
+    pub fn open_read_only(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Ok(Store {
+            conn,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Open the database at `path`. If it is damaged, it is renamed to
+    /// `history.db.corrupt-<time>` and the newest backup that opens cleanly
+    /// takes its place. The message says what happened, for the user.
+    pub fn open_or_recover(path: &Path) -> (Option<Self>, Option<String>) {
+        let err = match Self::open(path) {
+            Ok(store) => return (Some(store), None),
+            Err(e) => e,
+        };
+        if !matches!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+        ) {
+            let msg = format!(
+                "cannot open {}: {err}; history is not saved",
+                path.display()
+            );
+            return (None, Some(msg));
+        }
+
+        let aside = with_suffix(
+            path,
+            &Local::now().format(".corrupt-%Y%m%d-%H%M%S").to_string(),
+        );
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::rename(with_suffix(path, suffix), with_suffix(&aside, suffix));
+        }
+        let damaged = format!(
+            "{} is damaged ({err}) and was kept as {}",
+            path.display(),
+            aside.display()
+        );
+
+        for n in 1..=BACKUP_COUNT {
+            let backup = backup_path(path, n);
+            let taken = fs::metadata(&backup)
+                .and_then(|m| m.modified())
+                .map(DateTime::<Local>::from);
+            let Ok(taken) = taken else {
+                continue;
+            };
+            if copy_atomically(&backup, path).is_ok()
+                && let Ok(store) = Self::open(path)
+            {
+                let msg = format!(
+                    "{damaged}; restored the backup from {}",
+                    taken.format("%Y-%m-%d %H:%M")
+                );
+                return (Some(store), Some(msg));
+            }
+            let _ = fs::remove_file(path);
+        }
+
+        let msg = format!("{damaged}; no usable backup, starting a new history");
+        (Self::open(path).ok(), Some(msg))
+    }
+
+    /// Take a backup if the newest one is older than a day. Returns true if
+    /// a backup was written.
+    pub fn backup_if_due(&self) -> io::Result<bool> {
+        let due = fs::metadata(backup_path(&self.path, 1))
+            .and_then(|m| m.modified())
+            .map(|t| t.elapsed().unwrap_or_default() >= BACKUP_INTERVAL)
+            .unwrap_or(true);
+        if due {
+            self.backup()?;
+        }
+        Ok(due)
+    }
+
+    /// Write a consistent copy of the database to a temp file, fsync it and
+    /// rename it to `history.db.1`, shifting older backups up by one
+    fn backup(&self) -> io::Result<()> {
+        let tmp = with_suffix(&self.path, ".tmp");
+        let _ = fs::remove_file(&tmp);
+        self.conn
+            .execute("VACUUM INTO ?1", [tmp.to_string_lossy()])
+            .map_err(io::Error::other)?;
+        File::open(&tmp)?.sync_all()?;
+        for n in (1..BACKUP_COUNT).rev() {
+            let older = backup_path(&self.path, n);
+            if older.exists() {
+                fs::rename(older, backup_path(&self.path, n + 1))?;
+            }
+        }
+        fs::rename(&tmp, backup_path(&self.path, 1))?;
+        sync_parent(&self.path)
+    }
+
+    /// Where the database lives
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Default database location, next to the other bathis data
+    pub fn db_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("bathis")
+            .join("history.db")
+    }
+
+    /// Changes whenever another connection commits to the database
+    pub fn data_version(&self) -> rusqlite::Result<i64> {
+        self.conn
+            .query_row("PRAGMA data_version", [], |row| row.get(0))
+    }
+
+    /// True once any samples or sessions have been written
+    pub fn has_history(&self) -> rusqlite::Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM samples) OR EXISTS (SELECT 1 FROM sessions)",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// The most recent `limit` samples of one series (None = pack), oldest first
+    pub fn load_samples(
+        &self,
+        battery: Option<&str>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<BatterySample>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SAMPLE_COLUMNS} FROM samples WHERE battery IS ?1 \
+             ORDER BY timestamp_ms DESC LIMIT ?2"
+        ))?;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let mut samples = stmt
+            .query_map(params![battery, limit], read_sample)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        samples.reverse();
+        Ok(samples)
+    }
+
+    /// Samples of one series (None = pack) taken at or after `since`,
+    /// oldest first
+    pub fn load_samples_since(
+        &self,
+        battery: Option<&str>,
+        since: DateTime<Local>,
+    ) -> rusqlite::Result<Vec<BatterySample>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SAMPLE_COLUMNS} FROM samples WHERE battery IS ?1 AND timestamp_ms >= ?2 \
+             ORDER BY timestamp_ms"
+        ))?;
+        stmt.query_map(params![battery, since.timestamp_millis()], read_sample)?
+            .collect()
+    }
+
+    /// All rollup buckets, per tier and series in time order
+    pub fn load_rollups(&self) -> rusqlite::Result<Vec<(Resolution, Option<String>, Rollup)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT resolution, battery, start_ms, count, seconds, capacity_min, capacity_max, \
+             capacity_avg, power_min, power_max, power_avg FROM rollups \
+             ORDER BY resolution, battery, start_ms",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let resolution: String = row.get(0)?;
+            let rollup = Rollup {
+                start: from_millis(row.get(2)?),
+                count: row.get(3)?,
+                seconds: row.get(4)?,
+                capacity: Stat {
+                    min: row.get(5)?,
+                    max: row.get(6)?,
+                    avg: row.get(7)?,
+                },
+                power_watts: Stat {
+                    min: row.get(8)?,
+                    max: row.get(9)?,
+                    avg: row.get(10)?,
+                },
+            };
+            Ok((Resolution::parse(&resolution), row.get(1)?, rollup))
+        })?;
+        let mut rollups = Vec::new();
+        for row in rows {
+            if let (Some(resolution), battery, rollup) = row? {
+                rollups.push((resolution, battery, rollup));
+            }
+        }
+        Ok(rollups)
+    }
+
+    /// All sleeps, oldest first
+    pub fn load_sleeps(&self) -> rusqlite::Result<Vec<SleepPeriod>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT start_ms, end_ms, start_capacity, end_capacity, on_battery FROM sleeps \
+             ORDER BY start_ms",
+        )?;
+        stmt.query_map([], |row| {
+            Ok(SleepPeriod {
+                start: from_millis(row.get(0)?),
+                end: from_millis(row.get(1)?),
+                start_capacity: row.get(2)?,
+                end_capacity: row.get(3)?,
+                on_battery: row.get(4)?,
+            })
+        })?
+        .collect()
+    }
+
+    /// Names of the individual batteries that have samples
+    pub fn battery_names(&self) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT battery FROM samples WHERE battery IS NOT NULL ORDER BY battery",
+        )?;
+        stmt.query_map([], |row| row.get(0))?.collect()
+    }
+
+    /// All sessions of `kind` with their samples, oldest first
+    pub fn load_sessions(
+        &self,
+        kind: SessionKind,
+    ) -> rusqlite::Result<Vec<(SessionRow, Vec<BatterySample>)>> {
+        self.query_sessions(kind, false)
+    }
+
+    /// Finished sessions of `kind` with their samples, oldest first
+    pub fn load_finished_sessions(
+        &self,
+        kind: SessionKind,
+    ) -> rusqlite::Result<Vec<(SessionRow, Vec<BatterySample>)>> {
+        self.query_sessions(kind, true)
+    }
+
+    fn query_sessions(
+        &self,
+        kind: SessionKind,
+        finished_only: bool,
+    ) -> rusqlite::Result<Vec<(SessionRow, Vec<BatterySample>)>> {
+        let filter = if finished_only { " AND active = 0" } else { "" };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, start_ms, end_ms, start_capacity, end_capacity, completed, charger, active \
+             FROM sessions WHERE kind = ?1{filter} ORDER BY start_ms"
+        ))?;
+        let rows = stmt
+            .query_map([kind_name(kind)], |row| {
+                let id: i64 = row.get(0)?;
+                let session = SessionRow {
+                    start_time: from_millis(row.get(1)?),
+                    end_time: row.get::<_, Option<i64>>(2)?.map(from_millis),
+                    start_capacity: row.get(3)?,
+                    end_capacity: row.get(4)?,
+                    completed: row.get(5)?,
+                    charger: row.get(6)?,
+                    active: row.get(7)?,
+                };
+                Ok((id, session))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut samples_stmt = self.conn.prepare(&format!(
+            "SELECT {SAMPLE_COLUMNS} FROM session_samples WHERE session_id = ?1 \
+             ORDER BY timestamp_ms"
+        ))?;
+        rows.into_iter()
+            .map(|(id, session)| {
+                let samples = samples_stmt
+                    .query_map([id], read_sample)?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok((session, samples))
+            })
+            .collect()
+    }
+
+    /// Apply a batch of changes atomically
+    pub fn write(&mut self, batch: &WriteBatch) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(&format!(
+                "INSERT INTO samples ({SAMPLE_COLUMNS}) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"
+            ))?;
+            for sample in &batch.samples {
+                insert.execute(params_from_iter(sample_params(sample)))?;
+            }
+        }
+
+        {
+            let mut delete = tx.prepare_cached(
+                "DELETE FROM rollups WHERE resolution = ?1 AND battery IS ?2 AND start_ms = ?3",
+            )?;
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO rollups (resolution, battery, start_ms, count, seconds, \
+                 capacity_min, capacity_max, capacity_avg, power_min, power_max, power_avg) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for (resolution, battery, rollup) in &batch.rollups {
+                let start_ms = rollup.start.timestamp_millis();
+                delete.execute(params![resolution.name(), battery, start_ms])?;
+                insert.execute(params![
+                    resolution.name(),
+                    battery,
+                    start_ms,
+                    rollup.count,
+                    rollup.seconds,
+                    rollup.capacity.min,
+                    rollup.capacity.max,
+                    rollup.capacity.avg,
+                    rollup.power_watts.min,
+                    rollup.power_watts.max,
+                    rollup.power_watts.avg,
+                ])?;
+            }
+        }
+
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR REPLACE INTO sleeps (start_ms, end_ms, start_capacity, end_capacity, \
+                 on_battery) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for sleep in &batch.sleeps {
+                insert.execute(params![
+                    sleep.start.timestamp_millis(),
+                    sleep.end.timestamp_millis(),
+                    sleep.start_capacity,
+                    sleep.end_capacity,
+                    sleep.on_battery,
+                ])?;
+            }
+        }
+
+        let mut active_ids = Vec::new();
+        for session in &batch.sessions {
+            let id = write_session(&tx, session)?;
+            if session.row.active {
+                active_ids.push(id);
+            }
+        }
+        // Sessions that were in progress last time but were dropped since
+        let previously_active: Vec<i64> = {
+            let mut stmt = tx.prepare("SELECT id FROM sessions WHERE active = 1")?;
+            stmt.query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?
+        };
+        for id in previously_active {
+            if !active_ids.contains(&id) {
+                tx.execute("DELETE FROM sessions WHERE id = ?1", [id])?;
+            }
+        }
+
+        for (battery, oldest) in &batch.keep_samples_from {
+            tx.execute(
+                "DELETE FROM samples WHERE battery IS ?1 AND timestamp_ms < ?2",
+                params![battery, oldest.timestamp_millis()],
+            )?;
+        }
+        for (resolution, battery, oldest) in &batch.keep_rollups_from {
+            tx.execute(
+                "DELETE FROM rollups WHERE resolution = ?1 AND battery IS ?2 AND start_ms < ?3",
+                params![resolution.name(), battery, oldest.timestamp_millis()],
+            )?;
+        }
+        if let Some(oldest) = batch.keep_sleeps_from {
+            tx.execute(
+                "DELETE FROM sleeps WHERE start_ms < ?1",
+                [oldest.timestamp_millis()],
+            )?;
+        }
+        for (kind, oldest) in &batch.keep_sessions_from {
+            tx.execute(
+                "DELETE FROM sessions WHERE kind = ?1 AND active = 0 AND start_ms < ?2",
+                params![
+                    kind_name(*kind),
+                    oldest.map_or(i64::MAX, |t| t.timestamp_millis())
+                ],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// All health snapshots, oldest first
+    pub fn load_health(&self) -> rusqlite::Result<Vec<HealthSnapshot>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT date, battery, cycle_count, energy_full_wh, energy_full_design_wh, \
+             technology, serial_number FROM health_snapshots ORDER BY date, battery",
+        )?;
+        stmt.query_map([], |row| {
+            Ok(HealthSnapshot {
+                date: row.get::<_, NaiveDate>(0)?,
+                health: BatteryHealth {
+                    battery: row.get(1)?,
+                    cycle_count: row.get(2)?,
+                    energy_full_wh: row.get(3)?,
+                    energy_full_design_wh: row.get(4)?,
+                    technology: row.get(5)?,
+                    serial_number: row.get(6)?,
+                },
+            })
+        })?
+        .collect()
+    }
+
+    /// Insert or replace health snapshots (one per battery per day)
+    pub fn write_health(&mut self, snapshots: &[&HealthSnapshot]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO health_snapshots (date, battery, cycle_count, \
+                 energy_full_wh, energy_full_design_wh, technology, serial_number) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for snapshot in snapshots {
+                let h = &snapshot.health;
+                stmt.execute(params![
+                    snapshot.date,
+                    h.battery,
+                    h.cycle_count,
+                    h.energy_full_wh,
+                    h.energy_full_design_wh,
+                    h.technology,
+                    h.serial_number,
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+}
+
+/// Insert or update a session row and append the samples not stored yet
+fn write_session(tx: &Transaction, session: &SessionWrite) -> rusqlite::Result<i64> {
+    let row = &session.row;
+    let id: i64 = tx.query_row(
+        "INSERT INTO sessions (kind, start_ms, end_ms, start_capacity, end_capacity, \
+         completed, charger, active) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) \
+         ON CONFLICT (kind, start_ms) DO UPDATE SET end_ms = excluded.end_ms, \
+         end_capacity = excluded.end_capacity, completed = excluded.completed, \
+         charger = excluded.charger, active = excluded.active \
+         RETURNING id",
+        params![
+            kind_name(session.kind),
+            row.start_time.timestamp_millis(),
+            row.end_time.map(|t| t.timestamp_millis()),
+            row.start_capacity,
+            row.end_capacity,
+            row.completed,
+            row.charger,
+            row.active,
+        ],
+        |r| r.get(0),
+    )?;
+
+    let stored: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM session_samples WHERE session_id = ?1",
+        [id],
+        |r| r.get(0),
+    )?;
+    let mut insert = tx.prepare_cached(&format!(
+        "INSERT INTO session_samples (session_id, {SAMPLE_COLUMNS}) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"
+    ))?;
+    for sample in session.samples.iter().skip(stored as usize) {
+        let mut values = sample_params(sample);
+        values.insert(0, Box::new(id));
+        insert.execute(params_from_iter(values))?;
+    }
+    Ok(id)
+}
+
+/// `path` with `suffix` appended to the file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn backup_path(path: &Path, n: u32) -> PathBuf {
+    with_suffix(path, &format!(".{n}"))
+}
+
+/// Copy `from` to `to` through a temp file that is fsynced and renamed into
+/// place, so `to` is never left half-written
+fn copy_atomically(from: &Path, to: &Path) -> io::Result<()> {
+    let tmp = with_suffix(to, ".tmp");
+    fs::copy(from, &tmp)?;
+    File::open(&tmp)?.sync_all()?;
+    fs::rename(&tmp, to)?;
+    sync_parent(to)
+}
+
+/// Make a rename durable by syncing the containing directory
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+fn kind_name(kind: SessionKind) -> &'static str {
+    match kind {
+        SessionKind::Charge => "charge",
+        SessionKind::Discharge => "discharge",
+    }
+}
+
+fn from_millis(ms: i64) -> DateTime<Local> {
+    Local
+        .timestamp_millis_opt(ms)
+        .single()
+        .unwrap_or_else(Local::now)
+}
+
+fn sample_params(sample: &BatterySample) -> Vec<Box<dyn rusqlite::ToSql + '_>> {
+    let source = match sample.source {
+        EnergySource::Energy => "energy",
+        EnergySource::Charge => "charge",
+    };
+    let adapters = serde_json::to_string(&sample.adapters).unwrap_or_else(|_| "[]".into());
+    vec![
+        Box::new(sample.timestamp.timestamp_millis()),
+        Box::new(sample.battery.as_deref()),
+        Box::new(sample.capacity),
+        Box::new(sample.power_watts),
+        Box::new(sample.status.to_string()),
+        Box::new(sample.energy_now_wh),
+        Box::new(sample.energy_full_wh),
+        Box::new(sample.voltage_now_v),
+        Box::new(source),
+        Box::new(sample.temperature_c),
+        Box::new(adapters),
+    ]
+}
+
+fn read_sample(row: &Row) -> rusqlite::Result<BatterySample> {
+    let status: String = row.get(4)?;
+    let source: String = row.get(8)?;
+    let adapters: String = row.get(10)?;
+    Ok(BatterySample {
+        timestamp: from_millis(row.get(0)?),
+        battery: row.get(1)?,
+        capacity: row.get(2)?,
+        power_watts: row.get(3)?,
+        status: BatteryStatus::parse(&status),
+        energy_now_wh: row.get(5)?,
+        energy_full_wh: row.get(6)?,
+        voltage_now_v: row.get(7)?,
+        source: match source.as_str() {
+            "charge" => EnergySource::Charge,
+            _ => EnergySource::Energy,
+        },
+        temperature_c: row.get(9)?,
+        adapters: serde_json::from_str(&adapters).unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, temp_dir};
+
+    fn sample() -> BatterySample {
+        BatterySample {
+            voltage_now_v: 11.9,
+            temperature_c: Some(31.0),
+            ..test_support::sample(0, 55.0, -7.5, BatteryStatus::Discharging)
+        }
+    }
+
+    fn temp_db(name: &str) -> PathBuf {
+        temp_dir(name).join("history.db")
+    }
+
+    #[test]
+    fn damaged_database_is_replaced_by_backup() {
+        let path = temp_db("recover");
+        let sample = sample();
+        {
+            let mut store = Store::open(&path).unwrap();
+            let batch = WriteBatch {
+                samples: vec![&sample],
+                ..WriteBatch::default()
+            };
+            store.write(&batch).unwrap();
+            assert!(store.backup_if_due().unwrap());
+            assert!(!store.backup_if_due().unwrap());
+        }
+        fs::write(
+            &path,
+            "this is not a database, just text padding out a page",
+        )
+        .unwrap();
+
+        let (store, warning) = Store::open_or_recover(&path);
+        let samples = store.unwrap().load_samples(None, 10).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].temperature_c, Some(31.0));
+        assert!(warning.unwrap().contains("restored the backup"));
+
+        let dir = path.parent().unwrap();
+        let kept_aside = fs::read_dir(dir)
+            .unwrap()
+            .flatten()
+            .any(|e| e.file_name().to_string_lossy().contains(".corrupt-"));
+        assert!(kept_aside);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn read_only_store_loads_recent_samples_and_finished_sessions() {
+        let path = temp_db("readonly");
+        assert!(Store::open_read_only(&path).is_err());
+        assert!(!path.exists());
+
+        let recent = sample();
+        let mut old = sample();
+        old.timestamp = recent.timestamp - chrono::Duration::hours(2);
+        old.capacity = 80.0;
+        let row = |start_time, active| SessionRow {
+            start_time,
+            end_time: None,
+            start_capacity: 20.0,
+            end_capacity: 55.0,
+            completed: false,
+            charger: None,
+            active,
+        };
+        let samples = [recent.clone()];
+        {
+            let mut store = Store::open(&path).unwrap();
+            let batch = WriteBatch {
+                samples: vec![&old, &recent],
+                sessions: vec![
+                    SessionWrite {
+                        kind: SessionKind::Charge,
+                        row: row(old.timestamp, false),
+                        samples: &samples,
+                    },
+                    SessionWrite {
+                        kind: SessionKind::Charge,
+                        row: row(recent.timestamp, true),
+                        samples: &samples,
+                    },
+                ],
+                ..WriteBatch::default()
+            };
+            store.write(&batch).unwrap();
+        }
+
+        let store = Store::open_read_only(&path).unwrap();
+        let since = recent.timestamp - chrono::Duration::hours(1);
+        let loaded = store.load_samples_since(None, since).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].capacity, recent.capacity);
+        let finished = store.load_finished_sessions(SessionKind::Charge).unwrap();
+        assert_eq!(finished.len(), 1);
+        assert!(!finished[0].0.active);
+        assert_eq!(finished[0].1.len(), 1);
+        assert!(store.conn.execute("DELETE FROM samples", []).is_err());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn damaged_database_without_backup_starts_empty() {
+        let path = temp_db("nobackup");
+        fs::write(
+            &path,
+            "garbage garbage garbage garbage garbage garbage garbage",
+        )
+        .unwrap();
+
+        let (store, warning) = Store::open_or_recover(&path);
+        assert!(!store.unwrap().has_history().unwrap());
+        assert!(warning.unwrap().contains("no usable backup"));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
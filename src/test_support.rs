@@ -0,0 +1,39 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Local};
+
+use crate::battery::{BatterySample, BatteryStatus, EnergySource};
+
+/// Start of the period test samples are taken in
+pub fn start() -> DateTime<Local> {
+    DateTime::parse_from_rfc3339("2026-03-01T08:00:00+00:00")
+        .unwrap()
+        .with_timezone(&Local)
+}
+
+/// A pack sample `secs` into the test period, from a 50 Wh battery at 12 V.
+/// Tests needing other values override fields with struct update syntax.
+pub fn sample(secs: i64, capacity: f64, power_watts: f64, status: BatteryStatus) -> BatterySample {
+    BatterySample {
+        timestamp: start() + Duration::seconds(secs),
+        battery: None,
+        capacity,
+        power_watts,
+        status,
+        energy_now_wh: capacity / 100.0 * 50.0,
+        energy_full_wh: 50.0,
+        voltage_now_v: 12.0,
+        source: EnergySource::Energy,
+        temperature_c: None,
+        adapters: Vec::new(),
+    }
+}
+
+/// An empty directory of its own for the test `name`
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bathis-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -1,34 +1,29 @@
-use chrono::Timelike;
+use chrono::{DateTime, Local, Timelike};
 use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     symbols,
     text::{Line, Span},
-    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, List, ListItem, Paragraph, Wrap},
+    widgets::{
+        Axis, Block, Borders, Chart, Dataset, GraphType, List, ListItem, ListState, Paragraph, Wrap,
+    },
 };
 
-use crate::app::{App, View};
-use crate::battery::BatteryStatus;
+use crate::app::{App, ChartLines, SessionSort, View};
+use crate::battery::{BatteryStatus, EnergySource, describe_adapters};
+use crate::format::{format_duration, format_remaining};
+use crate::history::SessionKind;
+use crate::recorder::SessionChange;
 
 /// Render the entire UI
 pub fn render(f: &mut Frame, app: &App) {
     match app.view {
         View::Dashboard => render_dashboard(f, app),
         View::HistoryChart => render_history_chart(f, app),
-        View::SessionDetail(idx) => render_session_detail(f, app, idx),
-    }
-}
-
-/// Format seconds to a human-readable duration
-fn format_duration(secs: f64) -> String {
-    let total_secs = secs as u64;
-    let hours = total_secs / 3600;
-    let minutes = (total_secs % 3600) / 60;
-    if hours > 0 {
-        format!("{hours}h {minutes:02}m")
-    } else {
-        format!("{minutes}m")
+        View::SessionDetail(kind, start) => render_session_detail(f, app, kind, start),
+        View::Health => render_health(f, app),
+        View::SessionList => render_session_list(f, app),
     }
 }
 
@@ -71,14 +66,59 @@ fn render_dashboard(f: &mut Frame, app: &App) {
 }
 
 fn render_title_bar(f: &mut Frame, area: Rect, app: &App) {
-    let title = format!(" ⚡ bathis — {} ", app.battery_name);
-    let block = Paragraph::new(Line::from(vec![Span::styled(
+    let title = match app.view {
+        View::SessionDetail(..) | View::Health | View::SessionList => {
+            format!(" ⚡ bathis — {} ", app.battery_name)
+        }
+        _ if app.batteries.is_empty() => format!(" ⚡ bathis — {} ", app.battery_name),
+        _ => format!(
+            " ⚡ bathis — {}  [{}] ",
+            app.battery_name,
+            app.selection_label()
+        ),
+    };
+    let mut spans = vec![Span::styled(
         title,
         Style::default()
             .fg(Color::Cyan)
             .add_modifier(Modifier::BOLD),
-    )]))
-    .block(
+    )];
+    if app.history.is_following() {
+        let recorder = match app.recorder_pid {
+            Some(pid) => format!(" ◉ following recorder (pid {pid}) "),
+            None => " ◉ following recorder ".to_string(),
+        };
+        spans.push(Span::styled(recorder, Style::default().fg(Color::DarkGray)));
+    }
+    if let Some(event) = &app.last_event {
+        let change = match event.change {
+            SessionChange::Started => "started",
+            SessionChange::Ended => "ended",
+        };
+        spans.push(Span::styled(
+            format!(
+                " {} session {change} {} at {:.0}% ",
+                event.kind.label().to_lowercase(),
+                event.time.format("%H:%M"),
+                event.capacity
+            ),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    let warnings = [&app.history.load_warning, &app.health_log.load_warning];
+    for warning in warnings.into_iter().flatten() {
+        spans.push(Span::styled(
+            format!(" ⚠ {warning} "),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    for error in [&app.sample_error, &app.save_error].into_iter().flatten() {
+        spans.push(Span::styled(
+            format!(" ⚠ {error} "),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    let block = Paragraph::new(Line::from(spans)).block(
         Block::default()
             .borders(Borders::ALL)
             .border_style(Style::default().fg(Color::DarkGray)),
@@ -87,7 +127,7 @@ fn render_title_bar(f: &mut Frame, area: Rect, app: &App) {
 }
 
 fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
-    let sample = match &app.last_sample {
+    let sample = match app.current_sample() {
         Some(s) => s,
         None => {
             let msg = Paragraph::new("Waiting for first battery sample...")
@@ -122,7 +162,18 @@ fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
         format!("{:.2} W (discharging)", sample.power_watts)
     };
 
-    let info_lines = vec![
+    // Adapters are only recorded on the pack sample
+    let adapters = app
+        .last_sample
+        .as_ref()
+        .map(|s| s.adapters.as_slice())
+        .unwrap_or(&[]);
+    let supply_display = match describe_adapters(adapters) {
+        Some(adapter) => format!("on AC ({adapter})"),
+        None => "on battery".to_string(),
+    };
+
+    let mut info_lines = vec![
         Line::from(vec![
             Span::raw("  Status:   "),
             Span::styled(
@@ -132,6 +183,18 @@ fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
                     .add_modifier(Modifier::BOLD),
             ),
         ]),
+        Line::from(vec![
+            Span::raw("  Supply:   "),
+            Span::styled(supply_display, Style::default().fg(Color::White)),
+        ]),
+    ];
+    if let Some(thresholds) = &app.thresholds {
+        info_lines.push(Line::from(vec![
+            Span::raw("  Limits:   "),
+            Span::styled(thresholds.to_string(), Style::default().fg(Color::White)),
+        ]));
+    }
+    info_lines.extend([
         Line::from(""),
         Line::from(vec![
             Span::raw("  Battery:  "),
@@ -158,6 +221,17 @@ fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
             Span::raw("  Power:    "),
             Span::styled(power_display, Style::default().fg(Color::White)),
         ]),
+    ]);
+    if let Some(remaining) = app.remaining() {
+        info_lines.push(Line::from(vec![
+            Span::raw("  Left:     "),
+            Span::styled(
+                format_remaining(remaining),
+                Style::default().fg(Color::White),
+            ),
+        ]));
+    }
+    info_lines.extend([
         Line::from(vec![
             Span::raw("  Voltage:  "),
             Span::styled(
@@ -175,7 +249,41 @@ fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
                 Style::default().fg(Color::White),
             ),
         ]),
-    ];
+    ]);
+    if sample.source == EnergySource::Charge {
+        info_lines.push(Line::from(Span::styled(
+            format!("            (derived from {})", sample.source),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    if let Some(temp) = sample.temperature_c {
+        info_lines.push(Line::from(vec![
+            Span::raw("  Temp:     "),
+            Span::styled(
+                format!("{temp:.1} °C"),
+                Style::default().fg(if temp >= 45.0 {
+                    Color::Red
+                } else {
+                    Color::White
+                }),
+            ),
+        ]));
+    }
+
+    if let Some(sleep) = app.history.sleep_summary() {
+        info_lines.push(Line::from(vec![
+            Span::raw("  Sleep:    "),
+            Span::styled(
+                format!(
+                    "-{:.2} %/h over {} ({} sleeps)",
+                    sleep.drain_per_hour,
+                    format_duration(sleep.hours * 3600.0),
+                    sleep.count
+                ),
+                Style::default().fg(Color::White),
+            ),
+        ]));
+    }
 
     let info = Paragraph::new(info_lines)
         .block(
@@ -187,12 +295,12 @@ fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
     f.render_widget(info, chunks[0]);
 
     // Right: session history
-    let sessions = app.history.completed_sessions();
+    let sessions = app.history.sessions();
     let mut session_items: Vec<ListItem> = Vec::new();
 
     if sessions.is_empty() {
         session_items.push(ListItem::new(Line::from(Span::styled(
-            "  No completed charge sessions yet",
+            "  No charge sessions yet",
             Style::default().fg(Color::DarkGray),
         ))));
     } else {
@@ -201,17 +309,25 @@ fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
                 .end_time
                 .map(|e| (e - session.start_time).num_seconds() as f64)
                 .unwrap_or(0.0);
-            let line = format!(
-                "  [{}] {:.0}% → {:.0}%  ({})  {}",
+            let mut line = format!(
+                "  #{} {:.0}% → {:.0}%  ({})  {}",
                 i + 1,
                 session.start_capacity,
                 session.end_capacity,
                 format_duration(duration),
                 session.start_time.format("%m/%d %H:%M"),
             );
+            if !session.completed {
+                line.push_str("  partial");
+            }
+            let color = if session.completed {
+                Color::White
+            } else {
+                Color::DarkGray
+            };
             session_items.push(ListItem::new(Line::from(Span::styled(
                 line,
-                Style::default().fg(Color::White),
+                Style::default().fg(color),
             ))));
         }
     }
@@ -219,7 +335,8 @@ fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
     let sample_count = app.history.all_samples().len();
     let session_list =
         List::new(session_items).block(Block::default().borders(Borders::ALL).title(format!(
-            " Charge Sessions (90%+)  |  {} samples ",
+            " Charge Sessions (target {:.0}%)  |  {} samples ",
+            app.history.completion_target(),
             sample_count
         )));
     f.render_widget(session_list, chunks[1]);
@@ -227,14 +344,24 @@ fn render_status_panel(f: &mut Frame, area: Rect, app: &App) {
 
 fn render_help_bar(f: &mut Frame, area: Rect, app: &App) {
     let help_text = match app.view {
-        View::Dashboard => " [h] History Chart  [1/2] Session Detail  [q] Quit ",
+        View::Dashboard => " [h] History Chart  [s] Sessions  [w] Health  [q] Quit ",
         View::HistoryChart => {
-            " [d] Dashboard  [←/→] Pan  [+/-] Zoom  [f] Fit  [1/2] Session  [q] Quit "
+            " [d] Dashboard  [←/→] Pan  [+/-] Zoom  [f] Fit  [s] Sessions  [q] Quit "
         }
-        View::SessionDetail(_) => {
-            " [d] Dashboard  [h] History  [←/→] Pan  [+/-] Zoom  [f] Fit  [q] Quit "
+        View::SessionDetail(..) => {
+            " [Esc] Sessions  [d] Dashboard  [←/→] Pan  [+/-] Zoom  [f] Fit  [q] Quit "
+        }
+        View::Health => " [d] Dashboard  [h] History  [q] Quit ",
+        View::SessionList => {
+            " [↑/↓] Select  [Enter] Open  [o] Sort  [r] Reverse  [t] Charge/Discharge  [d] Dashboard  [q] Quit "
         }
     };
+    let help_text = match app.view {
+        View::Dashboard | View::HistoryChart if !app.batteries.is_empty() => {
+            format!(" [b] Battery {help_text}")
+        }
+        _ => help_text.to_string(),
+    };
 
     let help = Paragraph::new(Line::from(Span::styled(
         help_text,
@@ -262,11 +389,31 @@ fn render_history_chart(f: &mut Frame, app: &App) {
         .split(f.area());
 
     render_title_bar(f, chunks[0], app);
-    render_capacity_chart(f, chunks[1], app, app.history.all_samples());
-    render_power_chart(f, chunks[2], app, app.history.all_samples());
+    render_capacity_chart(f, chunks[1], app, app.selected_samples());
+    render_power_and_temperature(f, chunks[2], app, app.selected_samples());
     render_help_bar(f, chunks[3], app);
 }
 
+/// Power chart, with a temperature chart beside it when the battery reports one
+fn render_power_and_temperature(
+    f: &mut Frame,
+    area: Rect,
+    app: &App,
+    samples: &[crate::battery::BatterySample],
+) {
+    if !samples.iter().any(|s| s.temperature_c.is_some()) {
+        render_power_chart(f, area, app, samples);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(area);
+    render_power_chart(f, chunks[0], app, samples);
+    render_temperature_chart(f, chunks[1], app, samples);
+}
+
 fn render_capacity_chart(
     f: &mut Frame,
     area: Rect,
@@ -280,47 +427,56 @@ fn render_capacity_chart(
         return;
     }
 
-    let data: Vec<(f64, f64)> = match app.view {
-        View::SessionDetail(_idx) => {
+    let lines = match app.view {
+        View::SessionDetail(..) => {
             let (t_start, t_end) = app.session_viewport.visible_range();
             let session_start = samples
                 .first()
                 .map(|s| app.time_to_x(&s.timestamp))
                 .unwrap_or(0.0);
-            samples
-                .iter()
-                .map(|s| (app.time_to_x(&s.timestamp) - session_start, s.capacity))
-                .filter(|(x, _)| *x >= t_start && *x <= t_end)
-                .collect()
+            ChartLines {
+                values: samples
+                    .iter()
+                    .map(|s| (app.time_to_x(&s.timestamp) - session_start, s.capacity))
+                    .filter(|(x, _)| *x >= t_start && *x <= t_end)
+                    .collect(),
+                ..ChartLines::default()
+            }
         }
-        _ => app.capacity_chart_data(samples),
+        _ => app.capacity_chart_data(),
+    };
+    let title = match app.view {
+        View::HistoryChart => format!(" Battery % — {} ", app.history_resolution().label()),
+        _ => " Battery % ".to_string(),
     };
 
-    if data.is_empty() {
+    if lines.values.is_empty() {
         let msg = Paragraph::new("No data in visible range (try [f] to fit)")
-            .block(Block::default().borders(Borders::ALL).title(" Battery % "));
+            .block(Block::default().borders(Borders::ALL).title(title));
         f.render_widget(msg, area);
         return;
     }
 
     let (vp_start, vp_end) = match app.view {
-        View::SessionDetail(_) => app.session_viewport.visible_range(),
+        View::SessionDetail(..) => app.session_viewport.visible_range(),
         _ => app.viewport.visible_range(),
     };
 
     let x_labels = time_axis_labels_for_range(app, vp_start, vp_end, samples);
 
-    let datasets = vec![
-        Dataset::default()
-            .name("Battery %")
-            .marker(symbols::Marker::Braille)
-            .graph_type(GraphType::Line)
-            .style(Style::default().fg(Color::Green))
-            .data(&data),
-    ];
+    let sleeps = chart_sleeps(app, samples);
+    let shading = sleep_shading(&sleeps, (vp_start, vp_end), (0.0, 100.0), area.width);
+    let mut datasets = vec![shading_dataset(&shading)];
+    datasets.extend(range_datasets(&lines, &sleeps));
+    datasets.extend(line_datasets(
+        "Battery %",
+        Color::Green,
+        &lines.values,
+        &sleeps,
+    ));
 
     let chart = Chart::new(datasets)
-        .block(Block::default().borders(Borders::ALL).title(" Battery % "))
+        .block(Block::default().borders(Borders::ALL).title(title))
         .x_axis(
             Axis::default()
                 .title("Time")
@@ -358,23 +514,26 @@ fn render_power_chart(
         return;
     }
 
-    let data: Vec<(f64, f64)> = match app.view {
-        View::SessionDetail(_idx) => {
+    let lines = match app.view {
+        View::SessionDetail(..) => {
             let (t_start, t_end) = app.session_viewport.visible_range();
             let session_start = samples
                 .first()
                 .map(|s| app.time_to_x(&s.timestamp))
                 .unwrap_or(0.0);
-            samples
-                .iter()
-                .map(|s| (app.time_to_x(&s.timestamp) - session_start, s.power_watts))
-                .filter(|(x, _)| *x >= t_start && *x <= t_end)
-                .collect()
+            ChartLines {
+                values: samples
+                    .iter()
+                    .map(|s| (app.time_to_x(&s.timestamp) - session_start, s.power_watts))
+                    .filter(|(x, _)| *x >= t_start && *x <= t_end)
+                    .collect(),
+                ..ChartLines::default()
+            }
         }
-        _ => app.power_chart_data(samples),
+        _ => app.power_chart_data(),
     };
 
-    if data.is_empty() {
+    if lines.values.is_empty() {
         let msg = Paragraph::new("No data in visible range (try [f] to fit)")
             .block(Block::default().borders(Borders::ALL).title(" Power (W) "));
         f.render_widget(msg, area);
@@ -382,14 +541,14 @@ fn render_power_chart(
     }
 
     let (vp_start, vp_end) = match app.view {
-        View::SessionDetail(_) => app.session_viewport.visible_range(),
+        View::SessionDetail(..) => app.session_viewport.visible_range(),
         _ => app.viewport.visible_range(),
     };
 
     // Dynamic y-axis bounds based on visible data, symmetric around zero
-    let min_power = data.iter().map(|(_, y)| *y).fold(f64::INFINITY, f64::min);
-    let max_power = data
-        .iter()
+    let all_points = || lines.values.iter().chain(&lines.min).chain(&lines.max);
+    let min_power = all_points().map(|(_, y)| *y).fold(f64::INFINITY, f64::min);
+    let max_power = all_points()
         .map(|(_, y)| *y)
         .fold(f64::NEG_INFINITY, f64::max);
     let y_margin = (max_power - min_power).abs() * 0.1 + 0.5;
@@ -402,20 +561,24 @@ fn render_power_chart(
     // Zero reference line across the visible x range
     let zero_line: Vec<(f64, f64)> = vec![(vp_start, 0.0), (vp_end, 0.0)];
 
-    let datasets = vec![
-        Dataset::default()
-            .name("Power")
-            .marker(symbols::Marker::Braille)
-            .graph_type(GraphType::Line)
-            .style(Style::default().fg(Color::Yellow))
-            .data(&data),
+    let sleeps = chart_sleeps(app, samples);
+    let shading = sleep_shading(&sleeps, (vp_start, vp_end), (y_min, y_max), area.width);
+    let mut datasets = vec![shading_dataset(&shading)];
+    datasets.extend(range_datasets(&lines, &sleeps));
+    datasets.extend(line_datasets(
+        "Power",
+        Color::Yellow,
+        &lines.values,
+        &sleeps,
+    ));
+    datasets.push(
         Dataset::default()
             .name("0W")
             .marker(symbols::Marker::Dot)
             .graph_type(GraphType::Line)
             .style(Style::default().fg(Color::DarkGray))
             .data(&zero_line),
-    ];
+    );
 
     let chart = Chart::new(datasets)
         .block(
@@ -447,6 +610,182 @@ fn render_power_chart(
     f.render_widget(chart, area);
 }
 
+/// Dim min and max lines behind a chart drawn from rollups
+fn range_datasets<'a>(lines: &'a ChartLines, sleeps: &[(f64, f64)]) -> Vec<Dataset<'a>> {
+    [("min", &lines.min), ("max", &lines.max)]
+        .into_iter()
+        .filter(|(_, data)| !data.is_empty())
+        .flat_map(|(name, data)| line_datasets(name, Color::DarkGray, data, sleeps))
+        .collect()
+}
+
+/// A line broken wherever a sleep starts between two points, so nothing is
+/// drawn across time the machine was suspended. Only the first piece is
+/// named, to keep the legend to one entry.
+fn line_datasets<'a>(
+    name: &'a str,
+    color: Color,
+    data: &'a [(f64, f64)],
+    sleeps: &[(f64, f64)],
+) -> Vec<Dataset<'a>> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for i in 1..data.len() {
+        let (from, to) = (data[i - 1].0, data[i].0);
+        if sleeps.iter().any(|&(s, _)| s >= from && s < to) {
+            pieces.push(&data[start..i]);
+            start = i;
+        }
+    }
+    pieces.push(&data[start..]);
+
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(i, piece)| {
+            let dataset = Dataset::default()
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(color))
+                .data(piece);
+            if i == 0 { dataset.name(name) } else { dataset }
+        })
+        .collect()
+}
+
+/// Sleeps on a chart's x axis; session charts count from the session start
+fn chart_sleeps(app: &App, samples: &[crate::battery::BatterySample]) -> Vec<(f64, f64)> {
+    let offset = match app.view {
+        View::SessionDetail(..) => samples
+            .first()
+            .map(|s| app.time_to_x(&s.timestamp))
+            .unwrap_or(0.0),
+        _ => 0.0,
+    };
+    app.sleep_spans()
+        .into_iter()
+        .map(|(start, end)| (start - offset, end - offset))
+        .collect()
+}
+
+/// Bars filling the visible part of each sleep from `y_range.0` to
+/// `y_range.1`, about one per braille dot column of a chart `width` wide
+fn sleep_shading(
+    sleeps: &[(f64, f64)],
+    (vp_start, vp_end): (f64, f64),
+    (y_min, y_max): (f64, f64),
+    width: u16,
+) -> Vec<(f64, f64)> {
+    let step = (vp_end - vp_start) / (f64::from(width.max(1)) * 2.0);
+    let mut points = Vec::new();
+    // Too small a step no longer moves x forward
+    if step <= 0.0 || !step.is_finite() {
+        return points;
+    }
+    for &(start, end) in sleeps {
+        let (first, last) = (start.max(vp_start), end.min(vp_end));
+        if first > last {
+            continue;
+        }
+        let cols = ((last - first) / step) as usize;
+        for i in 0..=cols {
+            let x = first + i as f64 * step;
+            points.push((x, y_max));
+            if y_min < 0.0 {
+                points.push((x, y_min));
+            }
+        }
+    }
+    points
+}
+
+fn shading_dataset(points: &[(f64, f64)]) -> Dataset<'_> {
+    Dataset::default()
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Bar)
+        .style(Style::default().fg(Color::DarkGray))
+        .data(points)
+}
+
+fn render_temperature_chart(
+    f: &mut Frame,
+    area: Rect,
+    app: &App,
+    samples: &[crate::battery::BatterySample],
+) {
+    let data: Vec<(f64, f64)> = match app.view {
+        View::SessionDetail(..) => {
+            let (t_start, t_end) = app.session_viewport.visible_range();
+            let session_start = samples
+                .first()
+                .map(|s| app.time_to_x(&s.timestamp))
+                .unwrap_or(0.0);
+            samples
+                .iter()
+                .filter_map(|s| {
+                    Some((
+                        app.time_to_x(&s.timestamp) - session_start,
+                        s.temperature_c?,
+                    ))
+                })
+                .filter(|(x, _)| *x >= t_start && *x <= t_end)
+                .collect()
+        }
+        _ => app.temperature_chart_data(samples),
+    };
+
+    if data.is_empty() {
+        let msg = Paragraph::new("No data in visible range (try [f] to fit)")
+            .block(Block::default().borders(Borders::ALL).title(" Temp (°C) "));
+        f.render_widget(msg, area);
+        return;
+    }
+
+    let (vp_start, vp_end) = match app.view {
+        View::SessionDetail(..) => app.session_viewport.visible_range(),
+        _ => app.viewport.visible_range(),
+    };
+
+    // Round bounds out to whole 5 °C steps around the visible data
+    let min_temp = data.iter().map(|(_, y)| *y).fold(f64::INFINITY, f64::min);
+    let max_temp = data
+        .iter()
+        .map(|(_, y)| *y)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let y_min = (min_temp / 5.0).floor() * 5.0;
+    let y_max = ((max_temp / 5.0).ceil() * 5.0).max(y_min + 5.0);
+
+    let x_labels = time_axis_labels_for_range(app, vp_start, vp_end, samples);
+
+    let sleeps = chart_sleeps(app, samples);
+    let shading = sleep_shading(&sleeps, (vp_start, vp_end), (y_min, y_max), area.width);
+    let mut datasets = vec![shading_dataset(&shading)];
+    datasets.extend(line_datasets("Temp", Color::Red, &data, &sleeps));
+
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(" Temp (°C) "))
+        .x_axis(
+            Axis::default()
+                .title("Time")
+                .style(Style::default().fg(Color::Gray))
+                .bounds([vp_start, vp_end])
+                .labels(x_labels),
+        )
+        .y_axis(
+            Axis::default()
+                .title("°C")
+                .style(Style::default().fg(Color::Gray))
+                .bounds([y_min, y_max])
+                .labels(vec![
+                    Span::raw(format!("{:.0}", y_min)),
+                    Span::raw(format!("{:.0}", (y_min + y_max) / 2.0)),
+                    Span::raw(format!("{:.0}", y_max)),
+                ]),
+        );
+
+    f.render_widget(chart, area);
+}
+
 fn time_axis_labels_for_range(
     app: &App,
     start: f64,
@@ -455,7 +794,7 @@ fn time_axis_labels_for_range(
 ) -> Vec<Span<'static>> {
     // For session detail, offset from session start
     match app.view {
-        View::SessionDetail(_) => {
+        View::SessionDetail(..) => {
             let session_ref = samples.first().map(|s| s.timestamp);
             let n_labels = 5;
             let step = (end - start) / (n_labels as f64 - 1.0);
@@ -477,16 +816,21 @@ fn time_axis_labels_for_range(
 
 // --- Session Detail View ---
 
-fn render_session_detail(f: &mut Frame, app: &App, idx: usize) {
-    let sessions = app.history.completed_sessions();
-    if idx >= sessions.len() {
-        let msg = Paragraph::new(format!("Session {} not found", idx + 1))
-            .block(Block::default().borders(Borders::ALL));
+fn render_session_detail(f: &mut Frame, app: &App, kind: SessionKind, start: DateTime<Local>) {
+    // Looked up on each draw, since trimming may have dropped older sessions
+    let found = app
+        .history
+        .session_index(kind, start)
+        .and_then(|idx| Some((idx, app.history.session(kind, idx)?)));
+    let Some((idx, session)) = found else {
+        let msg = Paragraph::new(format!(
+            "The session from {} is no longer kept",
+            start.format("%Y-%m-%d %H:%M")
+        ))
+        .block(Block::default().borders(Borders::ALL));
         f.render_widget(msg, f.area());
         return;
-    }
-
-    let session = &sessions[idx];
+    };
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -502,30 +846,369 @@ fn render_session_detail(f: &mut Frame, app: &App, idx: usize) {
     render_title_bar(f, chunks[0], app);
 
     // Session info
-    let duration = session
-        .end_time
-        .map(|e| (e - session.start_time).num_seconds() as f64)
-        .unwrap_or(0.0);
-    let info_text = format!(
+    let samples = session.samples();
+    let (start_capacity, end_capacity) = (
+        samples.first().map(|s| s.capacity).unwrap_or(0.0),
+        samples.last().map(|s| s.capacity).unwrap_or(0.0),
+    );
+    let mut info_text = format!(
         "  Session {}  |  {:.0}% → {:.0}%  |  {}  |  Started: {}",
         idx + 1,
-        session.start_capacity,
-        session.end_capacity,
-        format_duration(duration),
-        session.start_time.format("%Y-%m-%d %H:%M"),
+        start_capacity,
+        end_capacity,
+        format_duration(session.duration_secs()),
+        session.start_time().format("%Y-%m-%d %H:%M"),
     );
+    match kind {
+        SessionKind::Charge => {
+            let charge = &app.history.sessions()[idx];
+            if let Some(charger) = &charge.charger {
+                info_text.push_str(&format!("  |  {charger}"));
+            }
+            if !charge.completed {
+                info_text.push_str("  |  partial");
+            }
+        }
+        SessionKind::Discharge => {
+            info_text.push_str(&format!(
+                "  |  Used {:.1} Wh  |  Avg {:.1} W  |  Peak {:.1} W",
+                session.energy_wh(),
+                session.average_watts(),
+                session.peak_watts(),
+            ));
+        }
+    }
     let info = Paragraph::new(Line::from(Span::styled(
         info_text,
         Style::default().fg(Color::Cyan),
     )))
-    .block(
+    .block(Block::default().borders(Borders::ALL).title(format!(
+        " {} Session {} ",
+        kind.label(),
+        idx + 1
+    )))
+    .wrap(Wrap { trim: false });
+    f.render_widget(info, chunks[1]);
+
+    render_capacity_chart(f, chunks[2], app, samples);
+    render_power_and_temperature(f, chunks[3], app, samples);
+    render_help_bar(f, chunks[4], app);
+}
+
+// --- Session List View ---
+
+fn render_session_list(f: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // title
+            Constraint::Min(5),    // session list
+            Constraint::Length(3), // help
+        ])
+        .split(f.area());
+
+    render_title_bar(f, chunks[0], app);
+
+    let kind = app.session_list_kind;
+    let count = app.history.session_count(kind);
+    let direction = if app.session_sort_ascending {
+        "↑"
+    } else {
+        "↓"
+    };
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        " {} Sessions ({})  |  sorted by {} {} ",
+        kind.label(),
+        count,
+        app.session_sort.label(),
+        direction
+    ));
+
+    if count == 0 {
+        let msg = Paragraph::new(Span::styled(
+            format!("  No {} sessions yet", kind.label().to_lowercase()),
+            Style::default().fg(Color::DarkGray),
+        ))
+        .block(block);
+        f.render_widget(msg, chunks[1]);
+        render_help_bar(f, chunks[2], app);
+        return;
+    }
+
+    let inner = block.inner(chunks[1]);
+    f.render_widget(block, chunks[1]);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(1)])
+        .split(inner);
+
+    // Highlight the column the list is sorted by
+    let header_style = |sort: SessionSort| {
+        let style = Style::default().fg(Color::DarkGray);
+        if app.session_sort == sort {
+            style.fg(Color::Cyan).add_modifier(Modifier::BOLD)
+        } else {
+            style
+        }
+    };
+    let (energy_header, last_header) = match kind {
+        SessionKind::Charge => ("Added", "   Charger"),
+        SessionKind::Discharge => ("Used", "    Peak W"),
+    };
+    let header = Line::from(vec![
+        Span::raw("    #  "),
+        Span::styled(
+            format!("{:<17}", "Started"),
+            header_style(SessionSort::Date),
+        ),
+        Span::styled(
+            format!("{:<11}", "Capacity"),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::styled(
+            format!("{:>11}", "Duration"),
+            header_style(SessionSort::Duration),
+        ),
+        Span::styled(
+            format!("{:>11}", energy_header),
+            header_style(SessionSort::Energy),
+        ),
+        Span::styled(
+            format!("{:>10}", "Avg W"),
+            header_style(SessionSort::AveragePower),
+        ),
+        Span::styled(last_header, Style::default().fg(Color::DarkGray)),
+    ]);
+    f.render_widget(Paragraph::new(header), rows[0]);
+
+    let items: Vec<ListItem> = app
+        .sorted_session_indices()
+        .into_iter()
+        .filter_map(|idx| Some((idx, app.history.session(kind, idx)?)))
+        .map(|(idx, session)| {
+            let samples = session.samples();
+            let mut line = format!(
+                "{:>5}  {:<17}{:>3.0}% → {:>3.0}%  {:>9}  {:>6.1} Wh  {:>6.1} W   ",
+                idx + 1,
+                session.start_time().format("%Y-%m-%d %H:%M"),
+                samples.first().map(|s| s.capacity).unwrap_or(0.0),
+                samples.last().map(|s| s.capacity).unwrap_or(0.0),
+                format_duration(session.duration_secs()),
+                session.energy_wh(),
+                session.average_watts(),
+            );
+            let mut color = Color::White;
+            match kind {
+                SessionKind::Charge => {
+                    let charge = &app.history.sessions()[idx];
+                    line.push_str(charge.charger.as_deref().unwrap_or("—"));
+                    if !charge.completed {
+                        line.push_str("  partial");
+                        color = Color::DarkGray;
+                    }
+                }
+                SessionKind::Discharge => {
+                    line.push_str(&format!("{:>6.1} W", session.peak_watts()));
+                }
+            }
+            ListItem::new(Line::from(Span::styled(line, Style::default().fg(color))))
+        })
+        .collect();
+
+    let list = List::new(items).highlight_style(
+        Style::default()
+            .bg(Color::DarkGray)
+            .fg(Color::White)
+            .add_modifier(Modifier::BOLD),
+    );
+    let mut state = ListState::default().with_selected(Some(app.session_list_selected));
+    f.render_stateful_widget(list, rows[1], &mut state);
+
+    render_help_bar(f, chunks[2], app);
+}
+
+// --- Health View ---
+
+/// Colors for per-battery series in the health charts
+const BATTERY_COLORS: [Color; 4] = [Color::Green, Color::Magenta, Color::Yellow, Color::Blue];
+
+fn render_health(f: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),                                  // title
+            Constraint::Length(app.health.len().max(1) as u16 + 3), // health table
+            Constraint::Percentage(50),                             // wear chart
+            Constraint::Percentage(50),                             // cycle chart
+            Constraint::Length(3),                                  // help
+        ])
+        .split(f.area());
+
+    render_title_bar(f, chunks[0], app);
+    render_health_table(f, chunks[1], app);
+
+    let batteries = app.health_log.batteries();
+    let first_date = app.health_log.snapshots.iter().map(|s| s.date).min();
+    let (Some(first_date), false) = (first_date, batteries.is_empty()) else {
+        for (area, title) in [(chunks[2], " Health % "), (chunks[3], " Cycle Count ")] {
+            let msg = Paragraph::new("No health snapshots yet")
+                .block(Block::default().borders(Borders::ALL).title(title));
+            f.render_widget(msg, area);
+        }
+        render_help_bar(f, chunks[4], app);
+        return;
+    };
+
+    // x = days since the first snapshot
+    let series = |value: &dyn Fn(&crate::health::BatteryHealth) -> Option<f64>| {
+        batteries
+            .iter()
+            .map(|name| {
+                let points: Vec<(f64, f64)> = app
+                    .health_log
+                    .series(name)
+                    .iter()
+                    .filter_map(|s| {
+                        let x = (s.date - first_date).num_days() as f64;
+                        Some((x, value(&s.health)?))
+                    })
+                    .collect();
+                (name.clone(), points)
+            })
+            .collect::<Vec<_>>()
+    };
+    let health_series = series(&|h| h.health_percent());
+    let cycle_series = series(&|h| h.cycle_count.map(f64::from));
+
+    let last_day = app
+        .health_log
+        .snapshots
+        .iter()
+        .map(|s| (s.date - first_date).num_days() as f64)
+        .fold(1.0, f64::max);
+    let x_labels: Vec<Span> = (0..5)
+        .map(|i| {
+            let day = last_day * i as f64 / 4.0;
+            let date = first_date + chrono::Duration::days(day as i64);
+            Span::raw(date.format("%Y-%m-%d").to_string())
+        })
+        .collect();
+
+    let min_health = health_series
+        .iter()
+        .flat_map(|(_, pts)| pts.iter().map(|(_, y)| *y))
+        .fold(100.0, f64::min);
+    let health_floor = ((min_health - 5.0) / 10.0).floor().clamp(0.0, 9.0) * 10.0;
+    render_health_chart(
+        f,
+        chunks[2],
+        " Health % (full / design) ",
+        &health_series,
+        [0.0, last_day],
+        [health_floor, 100.0],
+        x_labels.clone(),
+    );
+
+    let max_cycles = cycle_series
+        .iter()
+        .flat_map(|(_, pts)| pts.iter().map(|(_, y)| *y))
+        .fold(10.0, f64::max);
+    render_health_chart(
+        f,
+        chunks[3],
+        " Cycle Count ",
+        &cycle_series,
+        [0.0, last_day],
+        [0.0, (max_cycles * 1.1).ceil()],
+        x_labels,
+    );
+
+    render_help_bar(f, chunks[4], app);
+}
+
+fn render_health_table(f: &mut Frame, area: Rect, app: &App) {
+    let mut lines = vec![Line::from(Span::styled(
+        "  Battery  Technology  Cycles  Full / Design        Health  Wear   Serial",
+        Style::default().fg(Color::DarkGray),
+    ))];
+    if app.health.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "  No health data available",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    for h in &app.health {
+        let percent = |v: Option<f64>| v.map(|p| format!("{p:.1}%")).unwrap_or("—".into());
+        lines.push(Line::from(Span::styled(
+            format!(
+                "  {:<8} {:<11} {:>6}  {:>6.2} / {:>6.2} Wh  {:>6}  {:>5}  {}",
+                h.battery,
+                h.technology.as_deref().unwrap_or("—"),
+                h.cycle_count.map(|c| c.to_string()).unwrap_or("—".into()),
+                h.energy_full_wh,
+                h.energy_full_design_wh,
+                percent(h.health_percent()),
+                percent(h.wear_percent()),
+                h.serial_number.as_deref().unwrap_or("—"),
+            ),
+            Style::default().fg(Color::White),
+        )));
+    }
+
+    let table = Paragraph::new(lines).block(
         Block::default()
             .borders(Borders::ALL)
-            .title(format!(" Charge Session {} ", idx + 1)),
+            .title(" Battery Health "),
     );
-    f.render_widget(info, chunks[1]);
+    f.render_widget(table, area);
+}
 
-    render_capacity_chart(f, chunks[2], app, &session.samples);
-    render_power_chart(f, chunks[3], app, &session.samples);
-    render_help_bar(f, chunks[4], app);
+fn render_health_chart(
+    f: &mut Frame,
+    area: Rect,
+    title: &str,
+    series: &[(String, Vec<(f64, f64)>)],
+    x_bounds: [f64; 2],
+    y_bounds: [f64; 2],
+    x_labels: Vec<Span>,
+) {
+    let datasets: Vec<Dataset> = series
+        .iter()
+        .enumerate()
+        .map(|(i, (name, points))| {
+            Dataset::default()
+                .name(name.as_str())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(BATTERY_COLORS[i % BATTERY_COLORS.len()]))
+                .data(points)
+        })
+        .collect();
+
+    let mid = (y_bounds[0] + y_bounds[1]) / 2.0;
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title.to_string()),
+        )
+        .x_axis(
+            Axis::default()
+                .title("Date")
+                .style(Style::default().fg(Color::Gray))
+                .bounds(x_bounds)
+                .labels(x_labels),
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .bounds(y_bounds)
+                .labels(vec![
+                    Span::raw(format!("{:.0}", y_bounds[0])),
+                    Span::raw(format!("{:.0}", mid)),
+                    Span::raw(format!("{:.0}", y_bounds[1])),
+                ]),
+        );
+
+    f.render_widget(chart, area);
 }